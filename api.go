@@ -0,0 +1,224 @@
+package main
+
+import (
+	"context"
+	"errors"
+	"fmt"
+	"log/slog"
+	"maps"
+	"net"
+	"net/http"
+	"net/url"
+	"slices"
+	"strings"
+	"sync"
+	"time"
+)
+
+// The libdns provider always talks to this host and path prefix.
+const (
+	defaultAPIHost = "api.cloudflare.com"
+	defaultAPIPath = "/client/v4"
+)
+
+// apiTransport wraps every request made to the Cloudflare API. The libdns
+// provider always sends requests with http.DefaultClient, so this is
+// installed as that client's transport.
+type apiTransport struct {
+	base        http.RoundTripper
+	apiBase     *url.URL // replaces https://api.cloudflare.com/client/v4 if set
+	host        string   // Host header to send if set
+	contentType string   // replaces the provider's Content-Type if set
+	limiter     *rateLimiter
+	spacing     time.Duration // minimum time between writes
+
+	mu        sync.Mutex
+	warned    map[string]bool
+	nextWrite time.Time
+	calls     map[string]int // by method
+}
+
+func (t *apiTransport) RoundTrip(req *http.Request) (*http.Response, error) {
+	if t.limiter != nil {
+		if err := t.limiter.wait(req.Context()); err != nil {
+			return nil, err
+		}
+	}
+	t.mu.Lock()
+	if t.calls == nil {
+		t.calls = make(map[string]int)
+	}
+	t.calls[req.Method]++
+	t.mu.Unlock()
+	if t.spacing > 0 && req.Method != http.MethodGet && req.Method != http.MethodHead {
+		if err := t.waitToWrite(req.Context()); err != nil {
+			return nil, err
+		}
+	}
+	// A RoundTripper mustn't modify the caller's request.
+	req = req.Clone(req.Context())
+	// Some inspecting proxies answer with HTML unless JSON is asked for.
+	req.Header.Set("Accept", "application/json")
+	if t.contentType != "" && req.Header.Get("Content-Type") != "" {
+		req.Header.Set("Content-Type", t.contentType)
+	}
+	if t.apiBase != nil {
+		t.rebase(req)
+	}
+	if t.host != "" {
+		req.Host = t.host
+	}
+	resp, err := t.base.RoundTrip(req)
+	if err == nil {
+		t.checkWarnings(req, resp)
+	}
+	return resp, err
+}
+
+// callSummary describes the API calls made so far, like "DELETE=1 GET=2 PATCH=1".
+func (t *apiTransport) callSummary() string {
+	t.mu.Lock()
+	defer t.mu.Unlock()
+	var parts []string
+	for _, method := range slices.Sorted(maps.Keys(t.calls)) {
+		parts = append(parts, fmt.Sprintf("%s=%d", method, t.calls[method]))
+	}
+	return strings.Join(parts, " ")
+}
+
+// checkWarnings logs any deprecation or warning headers on resp, once per
+// distinct value, so an endpoint going away doesn't come as a surprise.
+func (t *apiTransport) checkWarnings(req *http.Request, resp *http.Response) {
+	for name, values := range resp.Header {
+		lower := strings.ToLower(name)
+		isWarning := lower == "deprecation" || lower == "sunset" || lower == "warning" ||
+			strings.HasPrefix(lower, "cf-") && (strings.Contains(lower, "deprecat") || strings.Contains(lower, "warn"))
+		if !isWarning {
+			continue
+		}
+		for _, value := range values {
+			key := name + ": " + value
+			t.mu.Lock()
+			seen := t.warned[key]
+			if t.warned == nil {
+				t.warned = make(map[string]bool)
+			}
+			t.warned[key] = true
+			t.mu.Unlock()
+			if !seen {
+				slog.Warn("Cloudflare API warning", "header", name, "value", value, "path", req.URL.Path)
+			}
+		}
+	}
+}
+
+// waitToWrite blocks until t.spacing has passed since the last write was
+// allowed through, or ctx is done.
+func (t *apiTransport) waitToWrite(ctx context.Context) error {
+	t.mu.Lock()
+	now := time.Now()
+	at := now
+	if t.nextWrite.After(now) {
+		at = t.nextWrite
+	}
+	t.nextWrite = at.Add(t.spacing)
+	t.mu.Unlock()
+
+	if !at.After(now) {
+		return nil
+	}
+	timer := time.NewTimer(at.Sub(now))
+	defer timer.Stop()
+	select {
+	case <-ctx.Done():
+		return ctx.Err()
+	case <-timer.C:
+		return nil
+	}
+}
+
+// rebase points req at t.apiBase if the provider addressed it to the
+// default API base.
+func (t *apiTransport) rebase(req *http.Request) {
+	rest, ok := strings.CutPrefix(req.URL.Path, defaultAPIPath)
+	if !ok || req.URL.Host != defaultAPIHost {
+		return
+	}
+	req.URL.Scheme = t.apiBase.Scheme
+	req.URL.Host = t.apiBase.Host
+	req.URL.Path = strings.TrimSuffix(t.apiBase.Path, "/") + rest
+	req.URL.RawPath = ""
+	req.Host = ""
+}
+
+// rateLimiter is a token bucket allowing rate requests per second, with
+// bursts of up to a second's worth of requests.
+type rateLimiter struct {
+	mu     sync.Mutex
+	rate   float64
+	burst  float64
+	tokens float64
+	last   time.Time
+}
+
+func newRateLimiter(rate float64) *rateLimiter {
+	burst := max(rate, 1)
+	return &rateLimiter{rate: rate, burst: burst, tokens: burst, last: time.Now()}
+}
+
+// wait takes a token, blocking until one is available or ctx is done.
+func (l *rateLimiter) wait(ctx context.Context) error {
+	l.mu.Lock()
+	now := time.Now()
+	l.tokens = min(l.burst, l.tokens+now.Sub(l.last).Seconds()*l.rate)
+	l.last = now
+	// Going negative reserves a future token, so waiters queue up fairly.
+	l.tokens--
+	delay := time.Duration(-l.tokens / l.rate * float64(time.Second))
+	l.mu.Unlock()
+
+	if delay <= 0 {
+		return nil
+	}
+	timer := time.NewTimer(delay)
+	defer timer.Stop()
+	select {
+	case <-ctx.Done():
+		return ctx.Err()
+	case <-timer.C:
+		return nil
+	}
+}
+
+const precheckTimeout = 5 * time.Second
+
+// precheck resolves and connects to the API host, so a link that's down
+// shows up as a plain network error rather than a failed API call.
+func precheck(ctx context.Context, apiBase *url.URL) error {
+	ctx, cancel := context.WithTimeout(ctx, precheckTimeout)
+	defer cancel()
+	host, port := defaultAPIHost, "443"
+	if apiBase != nil {
+		host, port = apiBase.Hostname(), apiBase.Port()
+		if port == "" && apiBase.Scheme == "http" {
+			port = "80"
+		} else if port == "" {
+			port = "443"
+		}
+	}
+	addrs, err := net.DefaultResolver.LookupHost(ctx, host)
+	if err != nil {
+		return fmt.Errorf("no network: could not resolve %s: %w", host, err)
+	}
+	// Like the dialer's own fallback, one broken address family shouldn't
+	// fail the check while another still works.
+	var errs []error
+	for _, addr := range addrs {
+		conn, err := (&net.Dialer{}).DialContext(ctx, "tcp", net.JoinHostPort(addr, port))
+		if err == nil {
+			return conn.Close()
+		}
+		errs = append(errs, err)
+	}
+	return fmt.Errorf("no network: could not connect to %s: %w", host, errors.Join(errs...))
+}
@@ -0,0 +1,45 @@
+package main
+
+import (
+	"context"
+	"testing"
+	"time"
+)
+
+func TestRateLimiter(t *testing.T) {
+	for _, tt := range []struct {
+		name     string
+		rate     float64
+		calls    int
+		min, max time.Duration
+	}{
+		// A second's worth of requests, or one if that's less, is a burst.
+		{"burst", 10, 10, 0, 50 * time.Millisecond},
+		{"slow burst", 0.5, 1, 0, 50 * time.Millisecond},
+		{"past the burst", 10, 13, 250 * time.Millisecond, time.Second},
+		{"past a smaller burst", 4, 6, 450 * time.Millisecond, 1500 * time.Millisecond},
+	} {
+		l := newRateLimiter(tt.rate)
+		start := time.Now()
+		for range tt.calls {
+			if err := l.wait(context.Background()); err != nil {
+				t.Fatalf("%s: wait: %v", tt.name, err)
+			}
+		}
+		if elapsed := time.Since(start); elapsed < tt.min || elapsed > tt.max {
+			t.Errorf("%s: %d calls at %v per second took %v, want %v to %v", tt.name, tt.calls, tt.rate, elapsed, tt.min, tt.max)
+		}
+	}
+}
+
+func TestRateLimiterCancel(t *testing.T) {
+	l := newRateLimiter(0.1)
+	if err := l.wait(context.Background()); err != nil {
+		t.Fatal(err)
+	}
+	ctx, cancel := context.WithTimeout(context.Background(), 10*time.Millisecond)
+	defer cancel()
+	if err := l.wait(ctx); err != context.DeadlineExceeded {
+		t.Errorf("wait for a token 10s away = %v, want %v", err, context.DeadlineExceeded)
+	}
+}
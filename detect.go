@@ -0,0 +1,436 @@
+package main
+
+import (
+	"bufio"
+	"bytes"
+	"context"
+	"errors"
+	"fmt"
+	"io"
+	"log/slog"
+	"maps"
+	"net"
+	"net/http"
+	"os"
+	"slices"
+	"strings"
+	"time"
+)
+
+const (
+	traceURLv4 = "https://1.1.1.1/cdn-cgi/trace"
+	traceURLv6 = "https://[2606:4700:4700::1111]/cdn-cgi/trace"
+)
+
+// traceClient is kept apart from http.DefaultClient, which carries the
+// Cloudflare API transport.
+var traceClient = &http.Client{}
+
+// traceSamples is CF_TRACE_SAMPLES, which run reads at startup.
+var traceSamples = 1
+
+// ipMap is the parsed CF_IP_MAP, which run reads at startup.
+var ipMap map[string]string
+
+// detectIPv4 asks Cloudflare's trace endpoint for our address over an
+// IPv4 literal, so the answer is always the IPv4 address.
+func detectIPv4(ctx context.Context) (net.IP, error) {
+	ip, err := sampleTrace(ctx, traceURLv4)
+	if err != nil {
+		return nil, err
+	}
+	if ip.To4() == nil {
+		return nil, fmt.Errorf("%s reported %s, which isn't an IPv4 address", traceURLv4, ip)
+	}
+	return ip, nil
+}
+
+// detectIPv6 is like detectIPv4, but over an IPv6 literal.
+func detectIPv6(ctx context.Context) (net.IP, error) {
+	ip, err := sampleTrace(ctx, traceURLv6)
+	if err != nil {
+		return nil, err
+	}
+	if ip.To4() != nil {
+		return nil, fmt.Errorf("%s reported %s, which isn't an IPv6 address", traceURLv6, ip)
+	}
+	return ip, nil
+}
+
+// sampleTrace asks url for our address CF_TRACE_SAMPLES times (once by
+// default) and returns the address more than half the samples agree on,
+// in case one anycast node gives an odd answer.
+func sampleTrace(ctx context.Context, url string) (net.IP, error) {
+	n := traceSamples
+	if n <= 1 {
+		return getIPFromTrace(ctx, url)
+	}
+	counts := make(map[string]int)
+	var errs []error
+	for range n {
+		ip, err := getIPFromTrace(ctx, url)
+		if err != nil {
+			errs = append(errs, err)
+			continue
+		}
+		counts[ip.String()]++
+	}
+	if len(counts) > 1 {
+		slog.Warn("trace samples disagree", "url", url, "counts", counts)
+	}
+	for addr, count := range counts {
+		if count > n/2 {
+			return net.ParseIP(addr), nil
+		}
+	}
+	errs = append(errs, fmt.Errorf("no address was reported by more than half of %d samples", n))
+	return nil, errors.Join(errs...)
+}
+
+func getIPFromTrace(ctx context.Context, url string) (net.IP, error) {
+	req, err := http.NewRequestWithContext(ctx, http.MethodGet, url, nil)
+	if err != nil {
+		return nil, err
+	}
+	resp, err := traceClient.Do(req)
+	if err != nil {
+		return nil, err
+	}
+	defer resp.Body.Close()
+	trace, err := parseTrace(resp.Body)
+	if err != nil {
+		return nil, fmt.Errorf("reading %s: %w", url, err)
+	}
+	ip := net.ParseIP(trace["ip"])
+	if ip == nil {
+		return nil, fmt.Errorf("no address found in %s", url)
+	}
+	// A foreign location usually means the request went out through a VPN.
+	if want := os.Getenv("CF_EXPECTED_LOC"); want != "" && !strings.EqualFold(trace["loc"], want) {
+		return nil, fmt.Errorf("%s placed %s in %q, not %q as CF_EXPECTED_LOC expects", url, ip, trace["loc"], want)
+	}
+	return ip, nil
+}
+
+// parseTrace reads the key=value lines of a /cdn-cgi/trace response.
+func parseTrace(r io.Reader) (map[string]string, error) {
+	trace := make(map[string]string)
+	scanner := bufio.NewScanner(r)
+	for scanner.Scan() {
+		if key, value, ok := strings.Cut(scanner.Text(), "="); ok {
+			trace[key] = value
+		}
+	}
+	return trace, scanner.Err()
+}
+
+// printTraceFile prints the address and other fields of a captured
+// /cdn-cgi/trace response, for debugging detection without the network.
+func printTraceFile(path string) error {
+	f, err := os.Open(path)
+	if err != nil {
+		return err
+	}
+	defer f.Close()
+	trace, err := parseTrace(f)
+	if err != nil {
+		return err
+	}
+	ip := net.ParseIP(trace["ip"])
+	if ip == nil {
+		return fmt.Errorf("no address found")
+	}
+	fmt.Printf("%s\t%s\n", addressType(ip), ip)
+	keys := slices.Sorted(maps.Keys(trace))
+	for _, key := range keys {
+		if key != "ip" {
+			fmt.Printf("%s=%s\n", key, trace[key])
+		}
+	}
+	return nil
+}
+
+const sourceTimeout = 10 * time.Second
+
+// cgnatRange is RFC 6598's shared address space, which carriers use between
+// their NAT and customers and which isn't reachable from the internet.
+var cgnatRange = &net.IPNet{IP: net.IPv4(100, 64, 0, 0), Mask: net.CIDRMask(10, 32)}
+
+// detectAddrs finds our address for each of recordTypes, using the sources
+// listed in CF_IP_SOURCE. policy is the CF_EMPTY_IP_POLICY for a family
+// that can't be detected, which run has already checked.
+func detectAddrs(ctx context.Context, recordTypes []string, policy string) ([]net.IP, error) {
+	sources := []string{"trace"}
+	if s := os.Getenv("CF_IP_SOURCE"); s != "" {
+		sources = strings.Split(s, ",")
+	}
+	var addrs []net.IP
+	var errs []error
+	for _, recordType := range recordTypes {
+		start := time.Now()
+		addr, source, err := detectFromSources(ctx, sources, recordType)
+		if err != nil && policy != "" && policy != "error" {
+			// A family that isn't detected has no record to set, so its
+			// existing record is left as it is.
+			slog.Warn("no address detected, leaving the record alone", "type", recordType, "policy", policy, "err", err)
+			explain("could not detect an %s address; action: leave the %s record alone, since CF_EMPTY_IP_POLICY=%s", recordType, recordType, policy)
+			errs = append(errs, fmt.Errorf("could not get %s address: %w", recordType, err))
+			continue
+		}
+		if err != nil {
+			return nil, fmt.Errorf("could not get %s address: %w", recordType, err)
+		}
+		slog.Debug("detection took", "type", recordType, "duration", time.Since(start))
+		slog.Info("detected address", "type", recordType, "value", addr, "source", source)
+		explain("detected %s address %s from %s", recordType, addr, source)
+		addrs = append(addrs, addr)
+	}
+	// With keep, a run that detects nothing at all leaves everything alone
+	// too, while with skip it fails.
+	if len(addrs) == 0 && policy != "keep" {
+		return nil, fmt.Errorf("could not detect any address: %w", errors.Join(errs...))
+	}
+	return addrs, nil
+}
+
+// detectAddrsRetrying is detectAddrs, tried again up to retries more times,
+// delay apart, while no address can be found, even under
+// CF_EMPTY_IP_POLICY=keep. This lets a run just after boot wait for the
+// link to come up.
+func detectAddrsRetrying(ctx context.Context, recordTypes []string, policy string, retries int, delay time.Duration) ([]net.IP, error) {
+	for attempt := 0; ; attempt++ {
+		addrs, err := detectAddrs(ctx, recordTypes, policy)
+		if (err == nil && len(addrs) > 0) || attempt >= retries {
+			return addrs, err
+		}
+		slog.Warn("detection failed, retrying", "err", err, "delay", delay, "remaining", retries-attempt)
+		timer := time.NewTimer(delay)
+		select {
+		case <-ctx.Done():
+			timer.Stop()
+			return nil, ctx.Err()
+		case <-timer.C:
+		}
+	}
+}
+
+// detectFromSources tries each source in order, giving each its own timeout,
+// and returns the first public address found along with its source. With
+// CF_IP_SOURCE_MODE=race, the sources are tried all at once instead.
+func detectFromSources(ctx context.Context, sources []string, recordType string) (net.IP, string, error) {
+	if os.Getenv("CF_IP_SOURCE_MODE") == "race" {
+		return raceSources(ctx, sources, recordType)
+	}
+	var errs []error
+	for _, source := range sources {
+		ip, err := detectPublicIP(ctx, source, recordType)
+		if err == nil {
+			return ip, source, nil
+		}
+		slog.Warn("IP source failed", "source", source, "type", recordType, "err", err)
+		errs = append(errs, fmt.Errorf("%s: %w", source, err))
+	}
+	return nil, "", errors.Join(errs...)
+}
+
+// raceSources asks all of sources at once and returns the first public
+// address found, cancelling the rest.
+func raceSources(ctx context.Context, sources []string, recordType string) (net.IP, string, error) {
+	ctx, cancel := context.WithCancel(ctx)
+	defer cancel()
+	type result struct {
+		ip     net.IP
+		source string
+		err    error
+	}
+	results := make(chan result, len(sources))
+	for _, source := range sources {
+		go func() {
+			ip, err := detectPublicIP(ctx, source, recordType)
+			results <- result{ip, source, err}
+		}()
+	}
+	var errs []error
+	for range sources {
+		r := <-results
+		if r.err == nil {
+			return r.ip, r.source, nil
+		}
+		slog.Warn("IP source failed", "source", r.source, "type", recordType, "err", r.err)
+		errs = append(errs, fmt.Errorf("%s: %w", r.source, r.err))
+	}
+	return nil, "", errors.Join(errs...)
+}
+
+// detectPublicIP is detectIP with the per-source timeout, rejecting
+// addresses that aren't reachable from the internet. An address CF_IP_MAP
+// rewrites is kept, since the published address is the mapped one.
+func detectPublicIP(ctx context.Context, source, recordType string) (net.IP, error) {
+	ctx, cancel := context.WithTimeout(ctx, sourceTimeout)
+	defer cancel()
+	ip, err := detectIP(ctx, source, recordType)
+	if err != nil {
+		return nil, err
+	}
+	if _, ok := ipMap[ip.String()]; ok {
+		return ip, nil
+	}
+	if !ip.IsGlobalUnicast() || ip.IsPrivate() || cgnatRange.Contains(ip) {
+		return nil, fmt.Errorf("%s is not a public address", ip)
+	}
+	return ip, nil
+}
+
+// detectIP finds our public address for recordType from source, which is
+// "trace" (the default), "stun:<server>", "route", or
+// "dns[:<host>@<resolver>]".
+func detectIP(ctx context.Context, source, recordType string) (net.IP, error) {
+	switch {
+	case source == "" || source == "trace":
+		switch recordType {
+		case "A":
+			return detectIPv4(ctx)
+		case "AAAA":
+			return detectIPv6(ctx)
+		default:
+			return nil, fmt.Errorf("unknown record type %v", recordType)
+		}
+	case strings.HasPrefix(source, "stun:"):
+		network := "udp4"
+		if recordType == "AAAA" {
+			network = "udp6"
+		}
+		return getIPFromSTUN(ctx, network, strings.TrimPrefix(source, "stun:"))
+	case source == "route":
+		return getIPFromRoute(ctx, recordType)
+	case source == "dns":
+		return getIPFromDNS(ctx, recordType, "myip.opendns.com", "resolver1.opendns.com")
+	case strings.HasPrefix(source, "dns:"):
+		host, server, ok := strings.Cut(strings.TrimPrefix(source, "dns:"), "@")
+		if !ok {
+			return nil, fmt.Errorf("IP source %q should look like dns:<host>@<resolver>", source)
+		}
+		return getIPFromDNS(ctx, recordType, host, server)
+	default:
+		return nil, fmt.Errorf("unknown IP source %q", source)
+	}
+}
+
+// getIPFromRoute returns the source address the routing table picks for
+// reaching Cloudflare's resolver. Connecting a UDP socket sends no packets,
+// so nothing leaves the host, but this only finds a public address if one
+// is assigned to it.
+func getIPFromRoute(ctx context.Context, recordType string) (net.IP, error) {
+	network, target := "udp4", "1.1.1.1:53"
+	if recordType == "AAAA" {
+		network, target = "udp6", "[2606:4700:4700::1111]:53"
+	}
+	conn, err := (&net.Dialer{}).DialContext(ctx, network, target)
+	if err != nil {
+		return nil, err
+	}
+	defer conn.Close()
+	return conn.LocalAddr().(*net.UDPAddr).IP, nil
+}
+
+// getIPFromDNS looks up host at server, over the address family for
+// recordType. Resolvers like OpenDNS answer myip.opendns.com with the
+// address the query came from.
+func getIPFromDNS(ctx context.Context, recordType, host, server string) (net.IP, error) {
+	family := "4"
+	if recordType == "AAAA" {
+		family = "6"
+	}
+	if _, _, err := net.SplitHostPort(server); err != nil {
+		server = net.JoinHostPort(server, "53")
+	}
+	resolver := &net.Resolver{
+		PreferGo: true,
+		Dial: func(ctx context.Context, network, _ string) (net.Conn, error) {
+			return (&net.Dialer{}).DialContext(ctx, network+family, server)
+		},
+	}
+	ips, err := resolver.LookupIP(ctx, "ip"+family, host)
+	if err != nil {
+		return nil, err
+	}
+	return ips[0], nil
+}
+
+// getIPsFromEnv reads the addresses that something else, such as router
+// firmware, put in the environment variable name. Several addresses (say,
+// one per WAN link) can be separated by commas or spaces.
+func getIPsFromEnv(name string) ([]net.IP, error) {
+	value := os.Getenv(name)
+	fields := strings.FieldsFunc(value, func(r rune) bool { return r == ',' || r == ' ' })
+	if len(fields) == 0 {
+		return nil, fmt.Errorf("%s is empty", name)
+	}
+	var ips []net.IP
+	for _, field := range fields {
+		ip := net.ParseIP(field)
+		if ip == nil {
+			return nil, fmt.Errorf("%s: %q is not an IP address", name, field)
+		}
+		ips = append(ips, ip)
+	}
+	return ips, nil
+}
+
+// addressType returns the record type that holds ip.
+func addressType(ip net.IP) string {
+	if ip.To4() != nil {
+		return "A"
+	}
+	return "AAAA"
+}
+
+// logPTR logs the reverse DNS names for ip, to help confirm at a glance
+// whose network a detected address belongs to.
+func logPTR(ctx context.Context, ip net.IP) {
+	names, err := net.DefaultResolver.LookupAddr(ctx, ip.String())
+	if err != nil {
+		slog.Warn("reverse DNS lookup failed", "value", ip, "err", err)
+		return
+	}
+	slog.Info("reverse DNS", "value", ip, "names", names)
+}
+
+// applyIPv6Suffix keeps the /64 prefix of addr and replaces its interface
+// identifier with suffix (such as "::1234"), for hosts whose prefix is
+// dynamic but whose suffix is stable.
+func applyIPv6Suffix(addr, suffix string) (string, error) {
+	ip := net.ParseIP(addr)
+	if ip == nil || ip.To4() != nil {
+		return "", fmt.Errorf("%q is not an IPv6 address", addr)
+	}
+	s := net.ParseIP(suffix)
+	if s == nil || s.To4() != nil {
+		return "", fmt.Errorf("IPv6 suffix %q is not an IPv6 address", suffix)
+	}
+	if !bytes.Equal(s[:8], net.IPv6zero[:8]) {
+		return "", fmt.Errorf("IPv6 suffix %q sets bits in the /64 prefix", suffix)
+	}
+	out := make(net.IP, net.IPv6len)
+	copy(out[:8], ip[:8])
+	copy(out[8:], s[8:])
+	return out.String(), nil
+}
+
+// parseIPMap reads a CF_IP_MAP value like "198.51.100.4=>203.0.113.7", for
+// 1:1 NAT where the detected address isn't the one to publish.
+func parseIPMap(s string) (map[string]string, error) {
+	m := make(map[string]string)
+	for _, entry := range strings.Split(s, ",") {
+		from, to, ok := strings.Cut(strings.TrimSpace(entry), "=>")
+		fromIP, toIP := net.ParseIP(strings.TrimSpace(from)), net.ParseIP(strings.TrimSpace(to))
+		if !ok || fromIP == nil || toIP == nil {
+			return nil, fmt.Errorf("invalid CF_IP_MAP entry %q: want detected=>published addresses", entry)
+		}
+		if addressType(fromIP) != addressType(toIP) {
+			return nil, fmt.Errorf("invalid CF_IP_MAP entry %q: both addresses must be IPv4 or both IPv6", entry)
+		}
+		m[fromIP.String()] = toIP.String()
+	}
+	return m, nil
+}
@@ -0,0 +1,136 @@
+package main
+
+import (
+	"context"
+	"maps"
+	"net"
+	"slices"
+	"strings"
+	"testing"
+)
+
+func TestDetectionSendsNoCredentials(t *testing.T) {
+	const token = "test-token-0123456789abcdef"
+	cf := newFakeCloudflare(t, "example.com")
+	trace := newFakeTrace(t, "ip=203.0.113.7\n")
+	t.Setenv("CLOUDFLARE_API_TOKEN", token)
+	t.Setenv("CF_API_BASE", cf.url)
+
+	runDyncf(t, "-ipv4-only", "-dns-domain", "home.example.com")
+	headers := trace.headers()
+	if len(headers) == 0 {
+		t.Fatal("no trace request was made")
+	}
+	for _, h := range headers {
+		for name, values := range h {
+			if strings.HasPrefix(strings.ToLower(name), "x-auth") || strings.EqualFold(name, "Authorization") {
+				t.Errorf("trace request has auth header %s", name)
+			}
+			for _, v := range values {
+				if strings.Contains(v, token) {
+					t.Errorf("trace request header %s holds the API token", name)
+				}
+			}
+		}
+	}
+}
+
+func TestApplyIPv6Suffix(t *testing.T) {
+	for _, tt := range []struct {
+		addr, suffix string
+		want         string // empty for an error
+	}{
+		{"2001:db8:1:2:aaaa:bbbb:cccc:dddd", "::1234:5678", "2001:db8:1:2::1234:5678"},
+		{"2001:db8:1:2::1", "::a:b:c:d", "2001:db8:1:2:a:b:c:d"},
+		{"2001:db8:1:2::1", "1::1", ""},
+		{"2001:db8:1:2::1", "0.0.0.1", ""},
+		{"2001:db8:1:2::1", "host", ""},
+		{"203.0.113.7", "::1", ""},
+	} {
+		got, err := applyIPv6Suffix(tt.addr, tt.suffix)
+		if (err != nil) != (tt.want == "") || got != tt.want {
+			t.Errorf("applyIPv6Suffix(%q, %q) = %q, %v; want %q", tt.addr, tt.suffix, got, err, tt.want)
+		}
+	}
+}
+
+func TestParseIPMap(t *testing.T) {
+	for _, tt := range []struct {
+		in   string
+		want map[string]string // nil for an error
+	}{
+		{"10.0.0.1=>203.0.113.7", map[string]string{"10.0.0.1": "203.0.113.7"}},
+		{" 10.0.0.1 => 203.0.113.7 , 2001:db8::0:1=>2001:db8::2", map[string]string{
+			"10.0.0.1":    "203.0.113.7",
+			"2001:db8::1": "2001:db8::2",
+		}},
+		{"10.0.0.1=>2001:db8::2", nil},
+		{"10.0.0.1=203.0.113.7", nil},
+		{"10.0.0.1=>", nil},
+		{"router=>203.0.113.7", nil},
+		{"", nil},
+	} {
+		got, err := parseIPMap(tt.in)
+		if (err != nil) != (tt.want == nil) || !maps.Equal(got, tt.want) {
+			t.Errorf("parseIPMap(%q) = %v, %v; want %v", tt.in, got, err, tt.want)
+		}
+	}
+}
+
+func TestDetectAddrsEmptyPolicy(t *testing.T) {
+	v4 := net.ParseIP("203.0.113.7")
+	// The trace server only ever reports an IPv4 address, so AAAA detection
+	// always fails.
+	for _, tt := range []struct {
+		policy      string
+		recordTypes []string
+		want        []net.IP
+		wantErr     bool
+	}{
+		{policy: "", recordTypes: []string{"A", "AAAA"}, wantErr: true},
+		{policy: "error", recordTypes: []string{"A", "AAAA"}, wantErr: true},
+		{policy: "skip", recordTypes: []string{"A", "AAAA"}, want: []net.IP{v4}},
+		{policy: "keep", recordTypes: []string{"A", "AAAA"}, want: []net.IP{v4}},
+		{policy: "skip", recordTypes: []string{"AAAA"}, wantErr: true},
+		{policy: "keep", recordTypes: []string{"AAAA"}},
+	} {
+		isolateEnv(t)
+		newFakeTrace(t, "ip=203.0.113.7\n")
+		got, err := detectAddrs(context.Background(), tt.recordTypes, tt.policy)
+		if (err != nil) != tt.wantErr || !slices.EqualFunc(got, tt.want, net.IP.Equal) {
+			t.Errorf("with CF_EMPTY_IP_POLICY=%q, detectAddrs(%q) = %v, %v; want %v, error %v", tt.policy, tt.recordTypes, got, err, tt.want, tt.wantErr)
+		}
+	}
+}
+
+func TestRunKeepsRecordsWhenNothingDetected(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	newFakeTrace(t, "ip=203.0.113.7\n")
+	t.Setenv("CLOUDFLARE_API_TOKEN", "test-token")
+	t.Setenv("CF_API_BASE", cf.url)
+	t.Setenv("CF_EMPTY_IP_POLICY", "keep")
+
+	if code := runDyncf(t, "-ipv6-only", "-unchanged-exit-code", "3", "-dns-domain", "home.example.com"); code != 3 {
+		t.Errorf("run exited with %d, want 3", code)
+	}
+	if reqs := cf.log(); len(reqs) != 0 {
+		t.Errorf("run made requests %q, want none", reqs)
+	}
+}
+
+func TestRunMapsCGNATAddress(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	newFakeTrace(t, "ip=100.64.1.2\n")
+	t.Setenv("CLOUDFLARE_API_TOKEN", "test-token")
+	t.Setenv("CF_API_BASE", cf.url)
+	t.Setenv("CF_IP_MAP", "100.64.1.2=>203.0.113.7")
+
+	// A carrier-grade NAT address isn't public, but one with a mapping is
+	// still detected, and the mapped address is published.
+	if code := runDyncf(t, "-ipv4-only", "-dns-domain", "home.example.com"); code != 0 {
+		t.Fatalf("run exited with %d", code)
+	}
+	if recs := cf.list("example.com"); len(recs) != 1 || recs[0].Content != "203.0.113.7" {
+		t.Errorf("records are %+v, want one holding 203.0.113.7", recs)
+	}
+}
@@ -0,0 +1,20 @@
+package main
+
+import (
+	"fmt"
+	"io"
+	"os"
+)
+
+var (
+	explainEnabled bool
+	explainOut     io.Writer = os.Stderr
+)
+
+// explain narrates a decision to stderr when -explain is passed, as plain
+// sentences alongside the structured logs.
+func explain(format string, args ...any) {
+	if explainEnabled {
+		fmt.Fprintf(explainOut, format+"\n", args...)
+	}
+}
@@ -0,0 +1,39 @@
+package main
+
+import (
+	"errors"
+	"os"
+	"syscall"
+)
+
+// heldLock keeps the lock file open, and so locked, until the process exits.
+// Without a reference its finalizer would close it.
+var heldLock *os.File
+
+// acquireLock takes an exclusive advisory lock on path. It returns false if
+// another process already holds it.
+func acquireLock(path string) (bool, error) {
+	f, err := os.OpenFile(path, os.O_RDWR|os.O_CREATE, 0o644)
+	if err != nil {
+		return false, err
+	}
+	err = syscall.Flock(int(f.Fd()), syscall.LOCK_EX|syscall.LOCK_NB)
+	if errors.Is(err, syscall.EWOULDBLOCK) {
+		f.Close()
+		return false, nil
+	}
+	if err != nil {
+		f.Close()
+		return false, err
+	}
+	heldLock = f
+	return true, nil
+}
+
+// releaseLock unlocks and closes the lock file taken by acquireLock.
+func releaseLock() {
+	if heldLock != nil {
+		heldLock.Close()
+		heldLock = nil
+	}
+}
@@ -1,90 +1,620 @@
 package main
 
 import (
-	"bufio"
 	"context"
+	"crypto/tls"
+	"errors"
 	"flag"
 	"fmt"
 	"log"
 	"log/slog"
 	"net"
 	"net/http"
+	"net/url"
 	"os"
+	"os/signal"
+	"slices"
+	"strconv"
 	"strings"
+	"syscall"
 	"time"
 
 	"github.com/libdns/cloudflare"
 	"github.com/libdns/libdns"
 )
 
-func getMyIP(recordType string) (net.IP, error) {
-	var netType string
-	switch recordType {
-	case "A":
-		netType = "tcp4"
-	case "AAAA":
-		netType = "tcp6"
-	default:
-		return nil, fmt.Errorf("unknown record type %v", recordType)
+// parseTTL reads a CF_TTL value. Cloudflare uses a TTL of 1 to mean
+// "automatic", so that's spelled "auto" rather than accepted as a number.
+func parseTTL(s string) (time.Duration, error) {
+	switch s {
+	case "":
+		return 5 * time.Minute, nil
+	case "auto":
+		return time.Second, nil
 	}
-	client := &http.Client{
-		Transport: &http.Transport{
-			DialContext: func(ctx context.Context, network string, addr string) (net.Conn, error) {
-				return (&net.Dialer{}).DialContext(ctx, netType, addr)
-			},
-		},
+	secs, err := strconv.Atoi(s)
+	if err != nil || secs < 60 || secs > 86400 {
+		return 0, fmt.Errorf("invalid TTL %q: use 60 to 86400 seconds, or \"auto\" for Cloudflare's automatic TTL (sent as 1)", s)
 	}
-	resp, err := client.Get("https://cloudflare.com/cdn-cgi/trace")
-	if err != nil {
-		return nil, err
+	return time.Duration(secs) * time.Second, nil
+}
+
+// parseHours reads a CF_ALLOWED_HOURS value like "0-6,22-23" into the set
+// of local hours it allows.
+func parseHours(s string) ([24]bool, error) {
+	var hours [24]bool
+	for _, part := range strings.Split(s, ",") {
+		from, to, isRange := strings.Cut(strings.TrimSpace(part), "-")
+		if !isRange {
+			to = from
+		}
+		start, err1 := strconv.Atoi(from)
+		end, err2 := strconv.Atoi(to)
+		if err1 != nil || err2 != nil || start < 0 || end > 23 || start > end {
+			return hours, fmt.Errorf("invalid CF_ALLOWED_HOURS %q: want hours or ranges from 0 to 23, like 0-6,22-23", s)
+		}
+		for h := start; h <= end; h++ {
+			hours[h] = true
+		}
 	}
-	scanner := bufio.NewScanner(resp.Body)
-	for scanner.Scan() {
-		if strings.HasPrefix(scanner.Text(), "ip=") {
-			return net.ParseIP(strings.TrimPrefix(scanner.Text(), "ip=")), nil
+	return hours, nil
+}
+
+// parseRecordTypes returns the address record types to detect and set:
+// those in a CF_RECORD_TYPES value, or both by default, unless -ipv4-only
+// or -ipv6-only picks one, which takes precedence.
+func parseRecordTypes(s string, ipv4Only, ipv6Only bool) ([]string, error) {
+	types := []string{"A", "AAAA"}
+	if s != "" {
+		types = nil
+		for _, t := range strings.Split(s, ",") {
+			t = strings.ToUpper(strings.TrimSpace(t))
+			if t != "A" && t != "AAAA" {
+				return nil, fmt.Errorf("invalid CF_RECORD_TYPES %q: want a list of A and AAAA", s)
+			}
+			if !slices.Contains(types, t) {
+				types = append(types, t)
+			}
 		}
 	}
-	return nil, fmt.Errorf("no address found")
+	switch {
+	case ipv4Only && ipv6Only:
+		return nil, errors.New("-ipv4-only and -ipv6-only can't be used together")
+	case ipv4Only:
+		return []string{"A"}, nil
+	case ipv6Only:
+		return []string{"AAAA"}, nil
+	}
+	return types, nil
 }
 
 func main() {
-	ctx := context.Background()
+	os.Exit(run())
+}
 
-	domain := flag.String("dns-domain", "", "Domain to update")
+// run does the work of main and returns the exit code, so that deferred
+// cleanup happens before the process exits.
+func run() int {
+	// Cancel in-flight requests when a supervisor like launchd or systemd
+	// asks us to stop.
+	ctx, stop := signal.NotifyContext(context.Background(), os.Interrupt, syscall.SIGTERM)
+	defer stop()
+
+	domain := flag.String("dns-domain", "", "Comma-separated domains to update")
+	content := flag.String("content", "", "Set this content instead of the detected addresses")
+	recordType := flag.String("type", "TXT", "Record type to set with -content")
+	ipv4Domain := flag.String("ipv4-domain", "", "Comma-separated domains that only get the A record")
+	ipv6Domain := flag.String("ipv6-domain", "", "Comma-separated domains that only get the AAAA record")
+	ptrTarget := flag.String("ptr-target", "", "Also point the reverse DNS of each address at this hostname")
+	ipv4Only := flag.Bool("ipv4-only", false, "Only detect and set the A record")
+	ipv6Only := flag.Bool("ipv6-only", false, "Only detect and set the AAAA record")
+	retryOnEmptyIP := flag.Int("retry-on-empty-ip", 0, "Retry address detection this many times before giving up")
+	retryDelay := flag.Duration("retry-delay", 10*time.Second, "How long to wait between detection retries")
+	verifyTimeout := flag.Duration("verify-timeout", 10*time.Minute, "How long verify-txt waits for the record to be visible")
+	yes := flag.Bool("yes", false, "Don't ask before deleting records")
+	confirmLargeChange := flag.Bool("confirm-large-change", false, "Allow deleting more than CF_MAX_DELETES records")
+	maxTTLWait := flag.Duration("max-ttl-wait", 0, "After changing records, wait up to this long for a public resolver to see them")
+	unchangedExitCode := flag.Int("unchanged-exit-code", 0, "Exit with this code if no record needed changing")
+	debug := flag.Bool("debug", false, "Enable debug logging")
+	flag.BoolVar(&explainEnabled, "explain", false, "Describe each decision on stderr")
+	showVersion := flag.Bool("version", false, "Print version information and exit")
 	flag.Parse()
 
-	parts := strings.Split(*domain, ".")
-	if len(parts) < 3 {
-		log.Fatalf("too few domain labels in %q", *domain)
+	if err := setupLogFile(); err != nil {
+		log.Fatalf("could not set up log file: %v", err)
+	}
+	if *debug {
+		slog.SetLogLoggerLevel(slog.LevelDebug)
+	}
+
+	recordTypes, err := parseRecordTypes(os.Getenv("CF_RECORD_TYPES"), *ipv4Only, *ipv6Only)
+	if err != nil {
+		log.Fatal(err)
+	}
+	emptyIPPolicy := os.Getenv("CF_EMPTY_IP_POLICY")
+	switch emptyIPPolicy {
+	case "", "error", "skip", "keep":
+	default:
+		log.Fatalf("invalid CF_EMPTY_IP_POLICY %q: want error, skip or keep", emptyIPPolicy)
+	}
+	if traceSamples, err = envInt("CF_TRACE_SAMPLES", 1); err != nil {
+		log.Fatal(err)
+	}
+	ipMap = nil
+	if s := os.Getenv("CF_IP_MAP"); s != "" {
+		if ipMap, err = parseIPMap(s); err != nil {
+			log.Fatal(err)
+		}
+	}
+
+	command := flag.Arg(0)
+	if *showVersion {
+		command = "version"
+	}
+	switch command {
+	case "version":
+		fmt.Println(versionString())
+		return 0
+	case "parse-trace":
+		if flag.NArg() != 2 {
+			log.Fatal("usage: dyncf parse-trace <file>")
+		}
+		if err := printTraceFile(flag.Arg(1)); err != nil {
+			log.Fatalf("could not parse %s: %v", flag.Arg(1), err)
+		}
+		return 0
+	case "delete":
+		if flag.NArg() != 3 {
+			log.Fatal("usage: dyncf delete <domain> <type>")
+		}
+		*domain = flag.Arg(1)
+	case "acme-set", "acme-clean":
+		if flag.NArg() != 3 && (command == "acme-set" || flag.NArg() != 2) {
+			log.Fatal("usage: dyncf acme-set <domain> <value>, or dyncf acme-clean <domain> [value]")
+		}
+		// A wildcard certificate is validated at the name it covers.
+		*domain = "_acme-challenge." + strings.TrimPrefix(flag.Arg(1), "*.")
+	case "verify-txt":
+		if flag.NArg() != 3 {
+			log.Fatal("usage: dyncf verify-txt <domain> <content>")
+		}
+		*domain = flag.Arg(1)
+	case "", "drift":
+	case "zone", "records":
+		if flag.NArg() != 2 {
+			log.Fatalf("usage: dyncf %s <domain>", command)
+		}
+		*domain = flag.Arg(1)
+	default:
+		log.Fatalf("unknown command %q", command)
+	}
+
+	if path := os.Getenv("CF_LOCK_FILE"); path != "" {
+		ok, err := acquireLock(path)
+		if err != nil {
+			log.Fatalf("could not lock %s: %v", path, err)
+		}
+		if !ok {
+			slog.Info("another run holds the lock, exiting", "path", path)
+			return 0
+		}
+		defer releaseLock()
+	}
+
+	// A pause file is a kill switch for scheduled runs that needs no
+	// change to the schedule itself.
+	if path := os.Getenv("CF_PAUSE_FILE"); path != "" && command == "" {
+		if _, err := os.Stat(path); err == nil {
+			slog.Info("paused, skipping this run", "path", path)
+			return *unchangedExitCode
+		}
+	}
+
+	// In detect-only mode, hand the addresses to another tool and leave
+	// Cloudflare alone.
+	if path := os.Getenv("CF_IP_OUT"); path != "" && command == "" {
+		addrs, err := detectAddrsRetrying(ctx, recordTypes, emptyIPPolicy, *retryOnEmptyIP, *retryDelay)
+		if err != nil {
+			log.Fatal(err)
+		}
+		if len(addrs) == 0 {
+			slog.Info("no address detected, leaving the file alone", "path", path, "policy", "keep")
+			return 0
+		}
+		if err := writeIPFile(path, os.Getenv("CF_IP_OUT_FORMAT"), addrs); err != nil {
+			log.Fatalf("could not write addresses to %s: %v", path, err)
+		}
+		return 0
+	}
+
+	var domains []string
+	if *domain != "" || (*ipv4Domain == "" && *ipv6Domain == "") {
+		domains = strings.Split(*domain, ",")
+	}
+	// Names given for one address family only get that family's record.
+	familyOf := make(map[string]string)
+	for _, f := range []struct{ recordType, list string }{{"A", *ipv4Domain}, {"AAAA", *ipv6Domain}} {
+		if f.list == "" {
+			continue
+		}
+		for _, d := range strings.Split(f.list, ",") {
+			familyOf[d] = f.recordType
+			domains = append(domains, d)
+		}
+	}
+	for _, d := range domains {
+		if strings.Count(d, ".") < 2 {
+			log.Fatalf("too few domain labels in %q; write the apex of a zone as @.example.com", d)
+		}
+	}
+	redactDomains = os.Getenv("CF_REDACT_DOMAINS") == "1"
+	if *ptrTarget != "" {
+		setupRedaction(append(slices.Clone(domains), *ptrTarget))
+	} else {
+		setupRedaction(domains)
 	}
-	zone := strings.Join(parts[len(parts)-2:], ".")
-	subdomain := strings.Join(parts[:len(parts)-2], ".")
-	slog.Info("parsed domain", "zone", zone, "subdomain", subdomain)
 
 	apiToken := os.Getenv("CLOUDFLARE_API_TOKEN")
 	if apiToken == "" {
 		log.Fatal("CLOUDFLARE_API_TOKEN env var is missing")
 	}
-	provider := cloudflare.Provider{APIToken: apiToken}
+	hideSecret(apiToken)
+	slog.Debug("using API token", "token", redactSecret(apiToken))
+	provider := &cloudflare.Provider{APIToken: apiToken}
+	// Zones on other accounts can have their own tokens.
+	zoneProviders := make(map[string]*cloudflare.Provider)
+	if s := os.Getenv("CF_ZONE_TOKENS"); s != "" {
+		for _, entry := range strings.Split(s, ",") {
+			zone, token, ok := strings.Cut(strings.TrimSpace(entry), "=")
+			if !ok || zone == "" || token == "" {
+				log.Fatal("invalid CF_ZONE_TOKENS: want a comma-separated list of zone=token")
+			}
+			hideSecret(token)
+			zoneProviders[strings.ToLower(zone)] = &cloudflare.Provider{APIToken: token}
+		}
+	}
+	// providerFor picks the provider for the most specific CF_ZONE_TOKENS
+	// zone that d is in, falling back to CLOUDFLARE_API_TOKEN.
+	providerFor := func(d string) *cloudflare.Provider {
+		labels := strings.Split(strings.ToLower(d), ".")
+		for i := range labels {
+			if p, ok := zoneProviders[strings.Join(labels[i:], ".")]; ok {
+				return p
+			}
+		}
+		return provider
+	}
 
-	var records []libdns.Record
-	for _, recordType := range []string{"A", "AAAA"} {
-		addr, err := getMyIP(recordType)
+	transport := &apiTransport{contentType: os.Getenv("CF_CONTENT_TYPE")}
+	if s := os.Getenv("CF_RATE_LIMIT"); s != "" {
+		rate, err := strconv.ParseFloat(s, 64)
+		if err != nil || !(rate > 0) {
+			log.Fatalf("invalid CF_RATE_LIMIT %q: want a number of requests per second above 0", s)
+		}
+		transport.limiter = newRateLimiter(rate)
+	}
+	if s := os.Getenv("CF_UPDATE_SPACING"); s != "" {
+		spacing, err := time.ParseDuration(s)
+		if err != nil || spacing < 0 {
+			log.Fatalf("invalid CF_UPDATE_SPACING %q: want a duration like 2s", s)
+		}
+		transport.spacing = spacing
+	}
+	maxDeletes, deleted = 0, 0
+	if !*confirmLargeChange {
+		n, err := envInt("CF_MAX_DELETES", 5)
+		if err != nil || n == 0 {
+			log.Fatalf("invalid CF_MAX_DELETES %q: want a number of records above 0", os.Getenv("CF_MAX_DELETES"))
+		}
+		maxDeletes = n
+	}
+	if s := os.Getenv("CF_API_BASE"); s != "" {
+		apiBase, err := url.Parse(s)
+		if err != nil || (apiBase.Scheme != "http" && apiBase.Scheme != "https") || apiBase.Host == "" {
+			log.Fatalf("invalid CF_API_BASE %q: want an http or https URL like https://api.cloudflare.com/client/v4", s)
+		}
+		transport.apiBase = apiBase
+	}
+	tlsConfig := &tls.Config{MinVersion: tls.VersionTLS12}
+	switch s := os.Getenv("CF_MIN_TLS"); s {
+	case "", "1.2":
+	case "1.3":
+		tlsConfig.MinVersion = tls.VersionTLS13
+	default:
+		log.Fatalf("invalid CF_MIN_TLS %q: want 1.2 or 1.3", s)
+	}
+	if host := os.Getenv("CF_API_HOST"); host != "" {
+		// Check the certificate against the real name, wherever CF_API_BASE
+		// actually sends the connection.
+		tlsConfig.ServerName = host
+		transport.host = host
+	}
+	base := http.DefaultTransport.(*http.Transport).Clone()
+	base.TLSClientConfig = tlsConfig
+	transport.base = base
+	http.DefaultClient.Transport = transport
+	if os.Getenv("CF_PRECHECK") == "1" {
+		if err := precheck(ctx, transport.apiBase); err != nil {
+			log.Fatal(err)
+		}
+	}
+
+	switch command {
+	case "delete":
+		if err := deleteRecords(ctx, providerFor(*domain), *domain, strings.ToUpper(flag.Arg(2)), *yes); err != nil {
+			log.Fatalf("could not delete %s records at %s: %v", strings.ToUpper(flag.Arg(2)), *domain, err)
+		}
+		return 0
+	case "zone", "records":
+		zone, err := findZone(ctx, providerFor(*domain), *domain)
+		if err != nil {
+			log.Fatalf("could not find zone for %s: %v", *domain, err)
+		}
+		if command == "zone" {
+			fmt.Println(zone)
+			return 0
+		}
+		if err := printRecords(ctx, providerFor(*domain), zone, recordName(*domain, zone)); err != nil {
+			log.Fatalf("could not list records for %s: %v", *domain, err)
+		}
+		return 0
+	}
+
+	ttl, err := parseTTL(os.Getenv("CF_TTL"))
+	if err != nil {
+		log.Fatal(err)
+	}
+	if s := os.Getenv("CF_PROPAGATION_WAIT"); s != "" && *maxTTLWait == 0 {
+		if *maxTTLWait, err = time.ParseDuration(s); err != nil {
+			log.Fatalf("invalid CF_PROPAGATION_WAIT %q: want a duration like 5m", s)
+		}
+	}
+
+	// ACME hooks get a single line they can check: the action and the name.
+	switch command {
+	case "acme-set":
+		created, err := addTXT(ctx, providerFor(*domain), *domain, flag.Arg(2), ttl)
+		if err != nil {
+			log.Fatalf("could not add TXT record to %s: %v", *domain, err)
+		}
+		act := actionUnchanged
+		if created {
+			act = actionCreated
+		}
+		fmt.Printf("%s\t%s\n", act, *domain)
+		return 0
+	case "acme-clean":
+		n, err := removeTXT(ctx, providerFor(*domain), *domain, flag.Arg(2))
 		if err != nil {
-			log.Fatalf("could not get v4 address: %v", err)
+			log.Fatalf("could not remove TXT records from %s: %v", *domain, err)
+		}
+		act := actionUnchanged
+		if n > 0 {
+			act = actionDeleted
 		}
+		fmt.Printf("%s\t%s\n", act, *domain)
+		return 0
+	}
+
+	if command == "verify-txt" {
+		if _, err := addTXT(ctx, providerFor(*domain), *domain, flag.Arg(2), ttl); err != nil {
+			log.Fatalf("could not add TXT record to %s: %v", *domain, err)
+		}
+		waitCtx, cancel := context.WithTimeout(ctx, *verifyTimeout)
+		defer cancel()
+		if err := waitForValue(waitCtx, *domain, "TXT", flag.Arg(2)); err != nil {
+			log.Fatalf("TXT record for %s isn't visible yet: %v", *domain, err)
+		}
+		return 0
+	}
+
+	// The desired records are worked out once and then given a name per
+	// domain, so detection happens once however many domains there are.
+	logPTRs := os.Getenv("CF_LOG_PTR") == "1"
+	var records []libdns.Record
+	if *content != "" {
 		records = append(records, libdns.Record{
-			Type:  recordType,
-			Name:  subdomain,
-			Value: addr.String(),
-			TTL:   5 * time.Minute,
+			Type:  *recordType,
+			Value: *content,
+			TTL:   ttl,
 		})
-		slog.Info("will set record", "type", recordType, "value", addr)
+		slog.Info("will set record", "type", *recordType, "value", *content, "source", "-content")
+		explain("using %s content %q from -content", *recordType, *content)
+	} else if name := os.Getenv("CF_IP_FROM_ENV"); name != "" {
+		addrs, err := getIPsFromEnv(name)
+		if err != nil {
+			log.Fatal(err)
+		}
+		for _, addr := range addrs {
+			addrType := addressType(addr)
+			if !slices.Contains(recordTypes, addrType) {
+				log.Fatalf("%s holds an address for an %s record, which is disabled", name, addrType)
+			}
+			records = append(records, libdns.Record{
+				Type:  addrType,
+				Value: addr.String(),
+				TTL:   ttl,
+			})
+			slog.Info("will set record", "type", addrType, "value", addr, "source", "$"+name)
+			explain("using %s address %s from $%s", addrType, addr, name)
+			if logPTRs {
+				logPTR(ctx, addr)
+			}
+		}
+	} else {
+		addrs, err := detectAddrsRetrying(ctx, recordTypes, emptyIPPolicy, *retryOnEmptyIP, *retryDelay)
+		if err != nil {
+			log.Fatal(err)
+		}
+		if len(addrs) == 0 {
+			slog.Info("no address detected, leaving every record alone", "policy", "keep")
+			return *unchangedExitCode
+		}
+		for _, addr := range addrs {
+			records = append(records, libdns.Record{
+				Type:  addressType(addr),
+				Value: addr.String(),
+				TTL:   ttl,
+			})
+			if logPTRs {
+				logPTR(ctx, addr)
+			}
+		}
 	}
 
-	result, err := provider.SetRecords(ctx, zone, records)
-	if err != nil {
-		log.Fatalf("could not update records: %v", err)
+	if *content == "" {
+		for i, rec := range records {
+			if published, ok := ipMap[rec.Value]; ok {
+				slog.Info("mapped address", "type", rec.Type, "detected", rec.Value, "value", published)
+				explain("mapped %s address %s to %s via CF_IP_MAP", rec.Type, rec.Value, published)
+				records[i].Value = published
+			}
+		}
+	}
+
+	if suffix := os.Getenv("CF_IPV6_SUFFIX"); suffix != "" && *content == "" {
+		for i, rec := range records {
+			if rec.Type != "AAAA" {
+				continue
+			}
+			value, err := applyIPv6Suffix(rec.Value, suffix)
+			if err != nil {
+				log.Fatal(err)
+			}
+			slog.Info("applied IPv6 suffix", "detected", rec.Value, "value", value)
+			explain("combined the /64 prefix of %s with suffix %s to get %s", rec.Value, suffix, value)
+			records[i].Value = value
+		}
+	}
+
+	// recordsFor returns the records to set at d: all of them, unless d was
+	// given for one address family.
+	recordsFor := func(d string) []libdns.Record {
+		recordType, ok := familyOf[d]
+		if !ok {
+			return records
+		}
+		return slices.DeleteFunc(slices.Clone(records), func(rec libdns.Record) bool { return rec.Type != recordType })
+	}
+
+	// drift only reports, so monitoring can run it with a read-only token.
+	// A check that fails exits with 2, so monitoring can tell it from drift.
+	if command == "drift" {
+		drifted, failed := false, false
+		for _, d := range domains {
+			drift, err := findDrift(ctx, providerFor(d), d, recordsFor(d))
+			if err != nil {
+				slog.Error("could not check domain", "domain", d, "err", err)
+				failed = true
+				continue
+			}
+			for _, line := range drift {
+				fmt.Println(line)
+			}
+			drifted = drifted || len(drift) > 0
+		}
+		switch {
+		case failed:
+			return 2
+		case drifted:
+			return 1
+		}
+		return 0
+	}
+
+	if os.Getenv("CF_RECORD_ID") != "" && (len(domains) != 1 || len(recordsFor(domains[0])) != 1) {
+		log.Fatal("CF_RECORD_ID needs one domain and one record to set; -ipv4-only or -ipv6-only can narrow detection down")
+	}
+
+	if s := os.Getenv("CF_ALLOWED_HOURS"); s != "" {
+		hours, err := parseHours(s)
+		if err != nil {
+			log.Fatal(err)
+		}
+		if hour := time.Now().Hour(); !hours[hour] {
+			for _, rec := range records {
+				slog.Info("deferring update outside CF_ALLOWED_HOURS", "type", rec.Type, "value", rec.Value, "hour", hour)
+			}
+			explain("the local hour is %d, which CF_ALLOWED_HOURS=%s doesn't allow; action: defer", hour, s)
+			return *unchangedExitCode
+		}
+	}
+
+	// One broken domain shouldn't stop the others from being updated.
+	var failed, skipped, deferred []string
+	changed, skippedRecords := false, false
+	skipUnknown := os.Getenv("CF_SKIP_UNKNOWN_ZONES") == "1"
+	deferOnOutage := os.Getenv("CF_DEFER_ON_OUTAGE") == "1"
+	for _, d := range domains {
+		domainChanged, domainSkipped, err := updateDomain(ctx, providerFor(d), d, recordsFor(d))
+		if skipUnknown && errors.Is(err, errNoZone) {
+			slog.Warn("skipping domain with no zone on this account", "domain", d)
+			skipped = append(skipped, d)
+			continue
+		}
+		if deferOnOutage && err != nil && outage(err) {
+			slog.Warn("Cloudflare appears to be down, leaving the update for the next run", "domain", d, "err", err)
+			deferred = append(deferred, d)
+			continue
+		}
+		if err != nil {
+			slog.Error("could not update domain", "domain", d, "err", err)
+			failed = append(failed, d)
+		}
+		changed = changed || domainChanged
+		skippedRecords = skippedRecords || domainSkipped
+	}
+	if *ptrTarget != "" {
+		for _, rec := range records {
+			if rec.Type != "A" && rec.Type != "AAAA" {
+				continue
+			}
+			ptrChanged, err := updatePTR(ctx, provider, rec.Value, *ptrTarget, ttl)
+			if err != nil {
+				slog.Error("could not update reverse DNS", "value", rec.Value, "err", err)
+				failed = append(failed, "PTR for "+rec.Value)
+			}
+			changed = changed || ptrChanged
+		}
+	}
+	if changed && *maxTTLWait > 0 {
+		waitCtx, cancel := context.WithTimeout(ctx, *maxTTLWait)
+		for _, d := range slices.DeleteFunc(slices.Clone(domains), func(d string) bool {
+			return slices.Contains(failed, d) || slices.Contains(skipped, d) || slices.Contains(deferred, d)
+		}) {
+			for _, rec := range recordsFor(d) {
+				if !canVerify(rec.Type) {
+					continue
+				}
+				if err := waitForValue(waitCtx, d, rec.Type, rec.Value); err != nil {
+					slog.Error("record isn't visible yet", "domain", d, "type", rec.Type, "value", rec.Value, "err", err)
+					failed = append(failed, fmt.Sprintf("propagation of %s %s", rec.Type, d))
+				}
+			}
+		}
+		cancel()
+	}
+	if len(failed) > 0 {
+		log.Fatalf("could not update %s", strings.Join(failed, ", "))
+	}
+	if path := os.Getenv("CF_BEACON_FILE"); path != "" && *content == "" {
+		// The beacon only claims addresses that every domain now has, so a
+		// run that left any domain or record for later doesn't write it.
+		if len(skipped) > 0 || len(deferred) > 0 || skippedRecords {
+			slog.Warn("not writing beacon, since some records weren't updated", "path", path)
+		} else {
+			var addrs []net.IP
+			for _, rec := range records {
+				addrs = append(addrs, net.ParseIP(rec.Value))
+			}
+			if err := writeBeacon(path, addrs, time.Now()); err != nil {
+				log.Fatalf("could not write beacon %s: %v", path, err)
+			}
+		}
+	}
+	slog.Info("done", "changed", changed, "skipped", skipped, "deferred", deferred, "calls", transport.callSummary())
+	if !changed {
+		return *unchangedExitCode
 	}
-	slog.Info("updated records", "records", result)
+	return 0
 }
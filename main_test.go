@@ -0,0 +1,567 @@
+package main
+
+import (
+	"encoding/json"
+	"flag"
+	"fmt"
+	"io"
+	"net/http"
+	"net/http/httptest"
+	"net/url"
+	"os"
+	"slices"
+	"strings"
+	"sync"
+	"testing"
+	"time"
+)
+
+// fakeCloudflare is an in-memory stand-in for the parts of the Cloudflare
+// API that the libdns provider uses: zone lookup, and listing, creating,
+// updating and deleting DNS records. It keeps a log of the requests made.
+type fakeCloudflare struct {
+	url       string
+	transport *apiTransport
+
+	mu       sync.Mutex
+	zones    map[string]string // name to ID
+	records  map[string][]fakeRecord
+	nextID   int
+	requests []string
+	bodies   []fakeRecord // decoded POST and PATCH bodies, in order
+	// nullLists makes an empty list come back as "result": null, as some
+	// endpoints do, rather than [].
+	nullLists bool
+}
+
+type fakeRecord struct {
+	ID      string `json:"id,omitempty"`
+	Type    string `json:"type,omitempty"`
+	Name    string `json:"name,omitempty"`
+	Content string `json:"content,omitempty"`
+	TTL     int    `json:"ttl,omitempty"`
+}
+
+// isolateEnv keeps the caller's own CF_ settings out of the test.
+func isolateEnv(t *testing.T) {
+	for _, kv := range os.Environ() {
+		if name, _, _ := strings.Cut(kv, "="); strings.HasPrefix(name, "CF_") {
+			t.Setenv(name, "")
+		}
+	}
+}
+
+// newFakeCloudflare starts a fake API serving zones and points
+// http.DefaultClient at it, as CF_API_BASE would. Tests that go through
+// run also need to set CF_API_BASE to its url, since run installs its own
+// transport.
+func newFakeCloudflare(t *testing.T, zones ...string) *fakeCloudflare {
+	t.Helper()
+	isolateEnv(t)
+	// The deletion limit is per run, and an earlier run may have set one.
+	maxDeletes, deleted = 0, 0
+	f := &fakeCloudflare{zones: make(map[string]string), records: make(map[string][]fakeRecord)}
+	for i, zone := range zones {
+		f.zones[zone] = fmt.Sprintf("zone%d", i+1)
+	}
+	srv := httptest.NewServer(f)
+	t.Cleanup(srv.Close)
+	f.url = srv.URL + defaultAPIPath
+	apiBase, err := url.Parse(f.url)
+	if err != nil {
+		t.Fatal(err)
+	}
+	f.transport = &apiTransport{base: http.DefaultTransport, apiBase: apiBase}
+	old := http.DefaultClient.Transport
+	http.DefaultClient.Transport = f.transport
+	t.Cleanup(func() { http.DefaultClient.Transport = old })
+	return f
+}
+
+// add creates a record at the fully-qualified name and returns its ID.
+func (f *fakeCloudflare) add(zone, recordType, name, content string, ttl int) string {
+	f.mu.Lock()
+	defer f.mu.Unlock()
+	f.nextID++
+	rec := fakeRecord{ID: fmt.Sprintf("rec%d", f.nextID), Type: recordType, Name: name, Content: content, TTL: ttl}
+	f.records[f.zones[zone]] = append(f.records[f.zones[zone]], rec)
+	return rec.ID
+}
+
+// list returns the records in zone.
+func (f *fakeCloudflare) list(zone string) []fakeRecord {
+	f.mu.Lock()
+	defer f.mu.Unlock()
+	return slices.Clone(f.records[f.zones[zone]])
+}
+
+// sent returns the bodies of the POST and PATCH requests made so far.
+func (f *fakeCloudflare) sent() []fakeRecord {
+	f.mu.Lock()
+	defer f.mu.Unlock()
+	return slices.Clone(f.bodies)
+}
+
+// log returns the requests made so far as "METHOD /path?query" and clears
+// the log.
+func (f *fakeCloudflare) log() []string {
+	f.mu.Lock()
+	defer f.mu.Unlock()
+	reqs := f.requests
+	f.requests = nil
+	return reqs
+}
+
+func (f *fakeCloudflare) ServeHTTP(w http.ResponseWriter, r *http.Request) {
+	f.mu.Lock()
+	defer f.mu.Unlock()
+	f.requests = append(f.requests, r.Method+" "+r.URL.RequestURI())
+
+	path := strings.TrimPrefix(r.URL.Path, defaultAPIPath)
+	parts := strings.Split(strings.Trim(path, "/"), "/")
+	switch {
+	case r.Method == http.MethodGet && path == "/zones":
+		var result []map[string]string
+		if id, ok := f.zones[r.URL.Query().Get("name")]; ok {
+			result = append(result, map[string]string{"id": id, "name": r.URL.Query().Get("name")})
+		}
+		writeResult(w, result)
+	case len(parts) >= 3 && parts[0] == "zones" && parts[2] == "dns_records":
+		zoneName := f.zoneName(parts[1])
+		if zoneName == "" {
+			writeError(w, http.StatusNotFound, "no such zone")
+			return
+		}
+		f.serveRecords(w, r, parts[1], zoneName, parts[3:])
+	default:
+		writeError(w, http.StatusNotFound, "unknown endpoint")
+	}
+}
+
+func (f *fakeCloudflare) serveRecords(w http.ResponseWriter, r *http.Request, zoneID, zoneName string, rest []string) {
+	recs := f.records[zoneID]
+	switch {
+	case r.Method == http.MethodGet && len(rest) == 0:
+		q := r.URL.Query()
+		result := []fakeRecord{}
+		for _, rec := range recs {
+			if (q.Get("type") == "" || rec.Type == q.Get("type")) &&
+				(q.Get("name") == "" || rec.Name == q.Get("name")) &&
+				(q.Get("content") == "" || rec.Content == q.Get("content")) {
+				result = append(result, rec)
+			}
+		}
+		if len(result) == 0 && f.nullLists {
+			writeResult(w, nil)
+			return
+		}
+		writeResult(w, result)
+	case r.Method == http.MethodPost && len(rest) == 0:
+		var rec fakeRecord
+		if !f.decode(w, r, &rec) {
+			return
+		}
+		// Like the real API, a record needs a name, which may be "@" for
+		// the apex or relative to the zone.
+		if rec.Name == "" {
+			writeError(w, http.StatusBadRequest, "DNS record name is required")
+			return
+		}
+		f.nextID++
+		rec.ID = fmt.Sprintf("rec%d", f.nextID)
+		rec.Name = fakeFQDN(rec.Name, zoneName)
+		f.records[zoneID] = append(recs, rec)
+		writeResult(w, rec)
+	case r.Method == http.MethodPatch && len(rest) == 1:
+		var patch fakeRecord
+		if !f.decode(w, r, &patch) {
+			return
+		}
+		i := slices.IndexFunc(recs, func(rec fakeRecord) bool { return rec.ID == rest[0] })
+		if i < 0 {
+			writeError(w, http.StatusNotFound, "no such record")
+			return
+		}
+		if patch.Name != "" {
+			recs[i].Name = fakeFQDN(patch.Name, zoneName)
+		}
+		if patch.Content != "" {
+			recs[i].Content = patch.Content
+		}
+		if patch.TTL != 0 {
+			recs[i].TTL = patch.TTL
+		}
+		writeResult(w, recs[i])
+	case r.Method == http.MethodDelete && len(rest) == 1:
+		i := slices.IndexFunc(recs, func(rec fakeRecord) bool { return rec.ID == rest[0] })
+		if i < 0 {
+			writeError(w, http.StatusNotFound, "no such record")
+			return
+		}
+		f.records[zoneID] = slices.Delete(recs, i, i+1)
+		writeResult(w, fakeRecord{ID: rest[0]})
+	default:
+		writeError(w, http.StatusMethodNotAllowed, "unsupported method")
+	}
+}
+
+func (f *fakeCloudflare) zoneName(id string) string {
+	for name, zoneID := range f.zones {
+		if zoneID == id {
+			return name
+		}
+	}
+	return ""
+}
+
+func (f *fakeCloudflare) decode(w http.ResponseWriter, r *http.Request, rec *fakeRecord) bool {
+	if err := json.NewDecoder(r.Body).Decode(rec); err != nil {
+		writeError(w, http.StatusBadRequest, err.Error())
+		return false
+	}
+	f.bodies = append(f.bodies, *rec)
+	return true
+}
+
+// fakeFQDN expands a record name the way Cloudflare does.
+func fakeFQDN(name, zone string) string {
+	switch {
+	case name == "@":
+		return zone
+	case name == zone || strings.HasSuffix(name, "."+zone):
+		return name
+	}
+	return name + "." + zone
+}
+
+func writeResult(w http.ResponseWriter, result any) {
+	w.Header().Set("Content-Type", "application/json")
+	json.NewEncoder(w).Encode(map[string]any{"success": true, "errors": []any{}, "result": result})
+}
+
+func writeError(w http.ResponseWriter, status int, message string) {
+	w.Header().Set("Content-Type", "application/json")
+	w.WriteHeader(status)
+	json.NewEncoder(w).Encode(map[string]any{
+		"success": false,
+		"errors":  []map[string]any{{"code": 1000, "message": message}},
+	})
+}
+
+// fakeTrace answers every trace request with body, whichever trace URL was
+// asked for, and keeps the requests it got.
+type fakeTrace struct {
+	mu       sync.Mutex
+	body     string
+	requests []*http.Request
+}
+
+// newFakeTrace points traceClient at a server answering with body.
+func newFakeTrace(t *testing.T, body string) *fakeTrace {
+	t.Helper()
+	f := &fakeTrace{body: body}
+	srv := httptest.NewServer(http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		f.mu.Lock()
+		f.requests = append(f.requests, r)
+		body := f.body
+		f.mu.Unlock()
+		io.WriteString(w, body)
+	}))
+	t.Cleanup(srv.Close)
+	target, err := url.Parse(srv.URL)
+	if err != nil {
+		t.Fatal(err)
+	}
+	old := traceClient.Transport
+	traceClient.Transport = redirectTransport{target}
+	t.Cleanup(func() { traceClient.Transport = old })
+	return f
+}
+
+// headers returns the headers of each request the trace server got.
+func (f *fakeTrace) headers() []http.Header {
+	f.mu.Lock()
+	defer f.mu.Unlock()
+	var headers []http.Header
+	for _, req := range f.requests {
+		headers = append(headers, req.Header)
+	}
+	return headers
+}
+
+func (f *fakeTrace) setBody(body string) {
+	f.mu.Lock()
+	defer f.mu.Unlock()
+	f.body = body
+}
+
+// redirectTransport sends every request to target instead.
+type redirectTransport struct {
+	target *url.URL
+}
+
+func (rt redirectTransport) RoundTrip(req *http.Request) (*http.Response, error) {
+	req = req.Clone(req.Context())
+	req.URL.Scheme = rt.target.Scheme
+	req.URL.Host = rt.target.Host
+	req.Host = ""
+	return http.DefaultTransport.RoundTrip(req)
+}
+
+// runDyncf runs dyncf with args, as if from the command line, and returns
+// its exit code.
+func runDyncf(t *testing.T, args ...string) int {
+	t.Helper()
+	oldArgs, oldFlags := os.Args, flag.CommandLine
+	t.Cleanup(func() { os.Args, flag.CommandLine = oldArgs, oldFlags })
+	os.Args = append([]string{"dyncf"}, args...)
+	flag.CommandLine = flag.NewFlagSet("dyncf", flag.ContinueOnError)
+	return run()
+}
+
+func TestRun(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	id := cf.add("example.com", "A", "home.example.com", "203.0.113.7", 300)
+	trace := newFakeTrace(t, "fl=1\nip=203.0.113.7\nloc=GB\n")
+	t.Setenv("CLOUDFLARE_API_TOKEN", "test-token")
+	t.Setenv("CF_API_BASE", cf.url)
+
+	// The record already holds the detected address, so the run finds the
+	// zone, lists its records, makes no writes and exits with the
+	// unchanged exit code.
+	if code := runDyncf(t, "-ipv4-only", "-unchanged-exit-code", "3", "-dns-domain", "home.example.com"); code != 3 {
+		t.Fatalf("first run exited with %d, want 3", code)
+	}
+	want := []string{
+		"GET /client/v4/zones?name=example.com",
+		"GET /client/v4/zones/zone1/dns_records",
+	}
+	if got := cf.log(); !slices.Equal(got, want) {
+		t.Errorf("first run made requests\n%q\nwant\n%q", got, want)
+	}
+
+	// Once the address changes, the record is updated in place.
+	trace.setBody("fl=1\nip=198.51.100.1\nloc=GB\n")
+	if code := runDyncf(t, "-ipv4-only", "-unchanged-exit-code", "3", "-dns-domain", "home.example.com"); code != 0 {
+		t.Fatalf("second run exited with %d, want 0", code)
+	}
+	want = []string{
+		"GET /client/v4/zones?name=example.com",
+		"GET /client/v4/zones/zone1/dns_records",
+		"PATCH /client/v4/zones/zone1/dns_records/" + id,
+	}
+	if got := cf.log(); !slices.Equal(got, want) {
+		t.Errorf("second run made requests\n%q\nwant\n%q", got, want)
+	}
+	wantRecs := []fakeRecord{{ID: id, Type: "A", Name: "home.example.com", Content: "198.51.100.1", TTL: 300}}
+	if got := cf.list("example.com"); !slices.Equal(got, wantRecs) {
+		t.Errorf("after second run, records are %+v, want %+v", got, wantRecs)
+	}
+}
+
+func TestRunCreatesMissingRecord(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	newFakeTrace(t, "ip=203.0.113.7\n")
+	t.Setenv("CLOUDFLARE_API_TOKEN", "test-token")
+	t.Setenv("CF_API_BASE", cf.url)
+	t.Setenv("CF_TTL", "120")
+
+	if code := runDyncf(t, "-ipv4-only", "-dns-domain", "home.example.com"); code != 0 {
+		t.Fatalf("run exited with %d", code)
+	}
+	recs := cf.list("example.com")
+	if len(recs) != 1 || recs[0].Type != "A" || recs[0].Name != "home.example.com" || recs[0].Content != "203.0.113.7" || recs[0].TTL != 120 {
+		t.Errorf("records are %+v, want one A record for home.example.com with 203.0.113.7 and TTL 120", recs)
+	}
+}
+
+func TestRunLooksUpEachZoneOnce(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	cf.add("example.com", "A", "a.example.com", "203.0.113.7", 300)
+	cf.add("example.com", "A", "b.example.com", "203.0.113.7", 300)
+	newFakeTrace(t, "ip=203.0.113.7\n")
+	t.Setenv("CLOUDFLARE_API_TOKEN", "test-token")
+	t.Setenv("CF_API_BASE", cf.url)
+
+	// The zone is looked up once and then cached, and each domain's records
+	// are listed once, whether or not anything has to change.
+	runDyncf(t, "-ipv4-only", "-dns-domain", "a.example.com,b.example.com")
+	want := []string{
+		"GET /client/v4/zones?name=example.com",
+		"GET /client/v4/zones/zone1/dns_records",
+		"GET /client/v4/zones/zone1/dns_records",
+	}
+	if got := cf.log(); !slices.Equal(got, want) {
+		t.Errorf("run made requests\n%q\nwant\n%q", got, want)
+	}
+}
+
+func TestRunDrift(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	cf.add("example.com", "TXT", "home.example.com", "v=spf1 -all", 300)
+	cf.add("example.com", "TXT", "home.example.com", "token", 300)
+	t.Setenv("CLOUDFLARE_API_TOKEN", "test-token")
+	t.Setenv("CF_API_BASE", cf.url)
+	t.Setenv("CF_TTL", "300")
+
+	for _, tt := range []struct {
+		name string
+		args []string
+		want int
+	}{
+		// A run would leave the SPF record alone, so it isn't drift.
+		{"other TXT records", []string{"-content", "token"}, 0},
+		{"missing record", []string{"-type", "A", "-content", "203.0.113.7"}, 1},
+		// A run would fail here, which isn't the same as drift.
+		{"ambiguous TXT records", []string{"-content", "other"}, 2},
+	} {
+		args := append(tt.args, "-dns-domain", "home.example.com", "drift")
+		if code := runDyncf(t, args...); code != tt.want {
+			t.Errorf("%s: drift exited with %d, want %d", tt.name, code, tt.want)
+		}
+	}
+	for _, req := range cf.log() {
+		if !strings.HasPrefix(req, "GET ") {
+			t.Errorf("drift made request %s", req)
+		}
+	}
+}
+
+func TestParseRecordTypes(t *testing.T) {
+	for _, tt := range []struct {
+		env                string
+		ipv4Only, ipv6Only bool
+		want               []string
+		wantErr            bool
+	}{
+		{env: "", want: []string{"A", "AAAA"}},
+		{env: "a", want: []string{"A"}},
+		{env: "AAAA, A, aaaa", want: []string{"AAAA", "A"}},
+		{env: "", ipv4Only: true, want: []string{"A"}},
+		{env: "", ipv6Only: true, want: []string{"AAAA"}},
+		// The flags take precedence over the variable.
+		{env: "AAAA", ipv4Only: true, want: []string{"A"}},
+		{env: "A", ipv6Only: true, want: []string{"AAAA"}},
+		// Each flag disables the other family, so together they leave none.
+		{env: "", ipv4Only: true, ipv6Only: true, wantErr: true},
+		{env: "A,AAAA", ipv4Only: true, ipv6Only: true, wantErr: true},
+		{env: "A,MX", wantErr: true},
+		{env: ",", wantErr: true},
+		{env: "MX", ipv4Only: true, wantErr: true},
+	} {
+		got, err := parseRecordTypes(tt.env, tt.ipv4Only, tt.ipv6Only)
+		if (err != nil) != tt.wantErr || !slices.Equal(got, tt.want) {
+			t.Errorf("parseRecordTypes(%q, %v, %v) = %q, %v; want %q, error %v", tt.env, tt.ipv4Only, tt.ipv6Only, got, err, tt.want, tt.wantErr)
+		}
+	}
+}
+
+func TestRunCreatesWithConfiguredTTL(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	newFakeTrace(t, "ip=203.0.113.7\n")
+	t.Setenv("CLOUDFLARE_API_TOKEN", "test-token")
+	t.Setenv("CF_API_BASE", cf.url)
+	t.Setenv("CF_TTL", "auto")
+
+	// A new record has nothing to copy a TTL from, so it gets CF_TTL's,
+	// which Cloudflare spells 1 for automatic.
+	if code := runDyncf(t, "-ipv4-only", "-dns-domain", "home.example.com"); code != 0 {
+		t.Fatalf("run exited with %d", code)
+	}
+	sent := cf.sent()
+	if len(sent) != 1 || sent[0].TTL != 1 {
+		t.Errorf("created records with bodies %+v, want one with TTL 1", sent)
+	}
+}
+
+func TestParseTTL(t *testing.T) {
+	for _, tt := range []struct {
+		in      string
+		want    time.Duration
+		wantErr bool
+	}{
+		{in: "", want: 5 * time.Minute},
+		{in: "auto", want: time.Second},
+		{in: "60", want: time.Minute},
+		{in: "86400", want: 24 * time.Hour},
+		{in: "1", wantErr: true},
+		{in: "59", wantErr: true},
+		{in: "86401", wantErr: true},
+		{in: "5m", wantErr: true},
+		{in: "Auto", wantErr: true},
+	} {
+		got, err := parseTTL(tt.in)
+		if (err != nil) != tt.wantErr || got != tt.want {
+			t.Errorf("parseTTL(%q) = %v, %v; want %v, error %v", tt.in, got, err, tt.want, tt.wantErr)
+		}
+	}
+}
+
+func TestParseHours(t *testing.T) {
+	for _, tt := range []struct {
+		in      string
+		want    []int
+		wantErr bool
+	}{
+		{in: "3", want: []int{3}},
+		{in: "0-6,22-23", want: []int{0, 1, 2, 3, 4, 5, 6, 22, 23}},
+		{in: " 1 , 4-5", want: []int{1, 4, 5}},
+		{in: "0-23", want: []int{0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23}},
+		{in: "24", wantErr: true},
+		{in: "-1", wantErr: true},
+		{in: "6-2", wantErr: true},
+		{in: "1,,2", wantErr: true},
+		{in: "night", wantErr: true},
+	} {
+		got, err := parseHours(tt.in)
+		if (err != nil) != tt.wantErr {
+			t.Errorf("parseHours(%q) error = %v, want error %v", tt.in, err, tt.wantErr)
+			continue
+		}
+		var hours []int
+		for h, ok := range got {
+			if ok {
+				hours = append(hours, h)
+			}
+		}
+		if !tt.wantErr && !slices.Equal(hours, tt.want) {
+			t.Errorf("parseHours(%q) allows hours %v, want %v", tt.in, hours, tt.want)
+		}
+	}
+}
+
+func TestRunBeacon(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	newFakeTrace(t, "ip=203.0.113.7\n")
+	t.Setenv("CLOUDFLARE_API_TOKEN", "test-token")
+	t.Setenv("CF_API_BASE", cf.url)
+	t.Setenv("CF_SKIP_UNKNOWN_ZONES", "1")
+	path := t.TempDir() + "/beacon.json"
+	t.Setenv("CF_BEACON_FILE", path)
+
+	// A domain skipped for having no zone means the beacon isn't written.
+	runDyncf(t, "-ipv4-only", "-dns-domain", "home.example.com,home.example.org")
+	if _, err := os.Stat(path); !os.IsNotExist(err) {
+		t.Errorf("beacon was written after a domain was skipped: %v", err)
+	}
+
+	// So does a record that CF_UPDATE_ONLY kept from being created.
+	t.Setenv("CF_UPDATE_ONLY", "1")
+	runDyncf(t, "-ipv4-only", "-dns-domain", "home.example.com")
+	if _, err := os.Stat(path); !os.IsNotExist(err) {
+		t.Errorf("beacon was written after a record was skipped: %v", err)
+	}
+
+	t.Setenv("CF_UPDATE_ONLY", "")
+	runDyncf(t, "-ipv4-only", "-dns-domain", "home.example.com")
+	data, err := os.ReadFile(path)
+	if err != nil {
+		t.Fatal(err)
+	}
+	var beacon struct {
+		IPv4 []string `json:"ipv4"`
+	}
+	if err := json.Unmarshal(data, &beacon); err != nil || !slices.Equal(beacon.IPv4, []string{"203.0.113.7"}) {
+		t.Errorf("beacon holds %s, want ipv4 [203.0.113.7]", data)
+	}
+}
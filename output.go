@@ -0,0 +1,93 @@
+package main
+
+import (
+	"bytes"
+	"encoding/json"
+	"fmt"
+	"net"
+	"os"
+	"path/filepath"
+	"time"
+)
+
+// writeIPFile writes addrs to path, or to stdout if path is "-", as
+// "plain" (one per line), "json" ({"ipv4": ..., "ipv6": ...}), or "env"
+// (IPV4=... lines).
+func writeIPFile(path, format string, addrs []net.IP) error {
+	var buf bytes.Buffer
+	switch format {
+	case "", "plain":
+		for _, addr := range addrs {
+			fmt.Fprintln(&buf, addr)
+		}
+	case "json":
+		out := make(map[string]string)
+		for _, addr := range addrs {
+			out[familyName(addr)] = addr.String()
+		}
+		if err := json.NewEncoder(&buf).Encode(out); err != nil {
+			return err
+		}
+	case "env":
+		for _, addr := range addrs {
+			if addr.To4() != nil {
+				fmt.Fprintf(&buf, "IPV4=%s\n", addr)
+			} else {
+				fmt.Fprintf(&buf, "IPV6=%s\n", addr)
+			}
+		}
+	default:
+		return fmt.Errorf("unknown format %q: want plain, json, or env", format)
+	}
+	if path == "-" {
+		_, err := os.Stdout.Write(buf.Bytes())
+		return err
+	}
+	return writeFileAtomic(path, buf.Bytes())
+}
+
+// writeBeacon records the published addresses in path as JSON, a list per
+// family, with the time they were confirmed, for local tools that want the
+// current public addresses without detecting them again.
+func writeBeacon(path string, addrs []net.IP, now time.Time) error {
+	out := map[string]any{"time": now.UTC().Format(time.RFC3339)}
+	for _, addr := range addrs {
+		family := familyName(addr)
+		list, _ := out[family].([]string)
+		out[family] = append(list, addr.String())
+	}
+	data, err := json.Marshal(out)
+	if err != nil {
+		return err
+	}
+	return writeFileAtomic(path, append(data, '\n'))
+}
+
+func familyName(ip net.IP) string {
+	if ip.To4() != nil {
+		return "ipv4"
+	}
+	return "ipv6"
+}
+
+// writeFileAtomic replaces path with data so readers never see a partly
+// written file.
+func writeFileAtomic(path string, data []byte) error {
+	f, err := os.CreateTemp(filepath.Dir(path), "."+filepath.Base(path)+".*")
+	if err != nil {
+		return err
+	}
+	defer os.Remove(f.Name())
+	if _, err := f.Write(data); err != nil {
+		f.Close()
+		return err
+	}
+	if err := f.Chmod(0o644); err != nil {
+		f.Close()
+		return err
+	}
+	if err := f.Close(); err != nil {
+		return err
+	}
+	return os.Rename(f.Name(), path)
+}
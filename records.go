@@ -0,0 +1,638 @@
+package main
+
+import (
+	"bufio"
+	"context"
+	"errors"
+	"fmt"
+	"log/slog"
+	"net"
+	"os"
+	"slices"
+	"strings"
+	"time"
+
+	"github.com/libdns/cloudflare"
+	"github.com/libdns/libdns"
+)
+
+// updateDomain sets records, which don't have a name yet, at domain. It
+// reports whether any record had to change, and whether any missing record
+// was skipped under CF_UPDATE_ONLY.
+func updateDomain(ctx context.Context, provider *cloudflare.Provider, domain string, records []libdns.Record) (changed, skipped bool, err error) {
+	start := time.Now()
+	zone, err := findZone(ctx, provider, domain)
+	if err != nil {
+		return false, false, err
+	}
+	slog.Debug("zone lookup took", "domain", domain, "duration", time.Since(start))
+	name := recordName(domain, zone)
+	lookup := "parent search"
+	if strings.HasPrefix(domain, "@.") {
+		lookup = "apex"
+	}
+	slog.Info("found zone", "domain", domain, "zone", zone, "subdomain", redactName(name), "lookup", lookup)
+
+	start = time.Now()
+	existing, err := provider.GetRecords(ctx, zone)
+	if err != nil {
+		return false, false, fmt.Errorf("listing records in %s: %w", zone, err)
+	}
+	slog.Debug("listing records took", "zone", zone, "duration", time.Since(start))
+
+	// A pinned record is updated in place, whatever its name, so duplicate
+	// names in the zone don't matter.
+	if id := os.Getenv("CF_RECORD_ID"); id != "" {
+		i := slices.IndexFunc(existing, func(rec libdns.Record) bool { return rec.ID == id })
+		if i < 0 {
+			return false, false, fmt.Errorf("no record with ID %s in %s", id, zone)
+		}
+		rec := records[0]
+		if existing[i].Type != rec.Type {
+			return false, false, fmt.Errorf("record %s is %s, not %s", id, existing[i].Type, rec.Type)
+		}
+		rec.Name = existing[i].Name
+		explain("matched record %s by ID from CF_RECORD_ID, rather than by name", id)
+		old, fqdn := existing[i], libdns.AbsoluteName(rec.Name, zone)
+		act := actionUnchanged
+		switch {
+		case old.Value != rec.Value:
+			act = actionUpdated
+			explain("found %s record %s with content %s; action: update to %s", rec.Type, fqdn, old.Value, rec.Value)
+		case old.TTL != rec.TTL:
+			act = actionSettingsUpdated
+			explain("found %s record %s with content %s and TTL %v; action: update the TTL to %v", rec.Type, fqdn, old.Value, old.TTL, rec.TTL)
+		default:
+			explain("found %s record %s with content %s; action: none", rec.Type, fqdn, old.Value)
+		}
+		if act.changed() {
+			if err := setRecord(ctx, provider, zone, rec, old); err != nil {
+				return false, false, fmt.Errorf("setting record %s: %w", id, err)
+			}
+		}
+		logUpsert(domain, rec, act, old.Value, "id", id, "match", "id")
+		return act.changed(), false, nil
+	}
+
+	// A CNAME only gets in the way of records we'd create.
+	creating := os.Getenv("CF_UPDATE_ONLY") != "1"
+	if creating && !slices.ContainsFunc(records, func(rec libdns.Record) bool { return rec.Type == "CNAME" }) {
+		if err := clearCNAME(ctx, provider, zone, name, existing); err != nil {
+			return false, false, err
+		}
+	}
+
+	// The records of each type are reconciled as a set, so that round-robin
+	// records are handled, and so are stray duplicates of a single record.
+	for _, set := range groupByType(records, name) {
+		start = time.Now()
+		setChanged, setSkipped, err := reconcileSet(ctx, provider, zone, existing, set)
+		changed, skipped = changed || setChanged, skipped || setSkipped
+		if err != nil {
+			return changed, skipped, fmt.Errorf("setting %s records: %w", set[0].Type, err)
+		}
+		slog.Debug("upserted records", "domain", domain, "type", set[0].Type, "duration", time.Since(start))
+	}
+	return changed, skipped, nil
+}
+
+// groupByType splits records into one set per type, in the order the types
+// first appear, naming each record name.
+func groupByType(records []libdns.Record, name string) [][]libdns.Record {
+	var sets [][]libdns.Record
+	for _, rec := range records {
+		rec.Name = name
+		i := slices.IndexFunc(sets, func(set []libdns.Record) bool { return set[0].Type == rec.Type })
+		if i < 0 {
+			sets = append(sets, []libdns.Record{rec})
+		} else {
+			sets[i] = append(sets[i], rec)
+		}
+	}
+	return sets
+}
+
+// findDrift describes each way the records at domain differ from records,
+// without changing anything. It follows the same plan as a run, so records
+// a run would leave alone, like other TXT records at the name, aren't drift.
+func findDrift(ctx context.Context, provider *cloudflare.Provider, domain string, records []libdns.Record) ([]string, error) {
+	zone, err := findZone(ctx, provider, domain)
+	if err != nil {
+		return nil, err
+	}
+	name := recordName(domain, zone)
+	existing, err := provider.GetRecords(ctx, zone)
+	if err != nil {
+		return nil, fmt.Errorf("listing records in %s: %w", zone, err)
+	}
+
+	var drift []string
+	if !slices.ContainsFunc(records, func(rec libdns.Record) bool { return rec.Type == "CNAME" }) {
+		if rec, ok := findRecord(existing, "CNAME", name); ok {
+			drift = append(drift, fmt.Sprintf("%s is a CNAME to %s", domain, rec.Value))
+		}
+	}
+	for _, set := range groupByType(records, name) {
+		plan, err := planSet(zone, existing, set, false)
+		if err != nil {
+			return nil, err
+		}
+		for _, c := range plan {
+			switch c.act {
+			case actionSettingsUpdated:
+				drift = append(drift, fmt.Sprintf("%s %s %s has TTL %v, want %v", c.rec.Type, domain, c.rec.Value, c.old.TTL, c.rec.TTL))
+			case actionUpdated:
+				drift = append(drift, fmt.Sprintf("%s %s is missing %s", c.rec.Type, domain, c.rec.Value))
+				drift = append(drift, fmt.Sprintf("%s %s has unwanted %s", c.rec.Type, domain, c.old.Value))
+			case actionCreated:
+				drift = append(drift, fmt.Sprintf("%s %s is missing %s", c.rec.Type, domain, c.rec.Value))
+			case actionDeleted:
+				drift = append(drift, fmt.Sprintf("%s %s has unwanted %s", c.rec.Type, domain, c.rec.Value))
+			}
+		}
+	}
+	return drift, nil
+}
+
+// action is what a run did to a record.
+type action string
+
+const (
+	actionCreated         action = "created"
+	actionUpdated         action = "updated"
+	actionSettingsUpdated action = "settings_updated" // same value, new TTL
+	actionUnchanged       action = "unchanged"
+	actionSkipped         action = "skipped"
+	actionDeleted         action = "deleted"
+)
+
+func (a action) changed() bool {
+	return a == actionCreated || a == actionUpdated || a == actionSettingsUpdated || a == actionDeleted
+}
+
+// logUpsert logs act, what was done to rec at domain, including the value
+// an update replaced.
+func logUpsert(domain string, rec libdns.Record, act action, from string, attrs ...any) {
+	attrs = append([]any{"domain", domain, "type", rec.Type, "value", rec.Value, "action", act}, attrs...)
+	if act == actionUpdated {
+		attrs = append(attrs, "from", from)
+	}
+	slog.Info("set record", attrs...)
+}
+
+// maxDeletes is the most records a run may delete, from CF_MAX_DELETES, or
+// 0 for no limit, and deleted is how many it has deleted so far.
+var maxDeletes, deleted int
+
+// reserveDeletes counts n deletions against the run's limit before any of
+// them is made, so that a bad list of records or a bug can't empty a zone,
+// and a run over the limit fails without deleting anything more.
+func reserveDeletes(n int) error {
+	if maxDeletes > 0 && deleted+n > maxDeletes {
+		return fmt.Errorf("refusing to delete %d records, which would take this run past CF_MAX_DELETES=%d; pass -confirm-large-change to allow it", n, maxDeletes)
+	}
+	deleted += n
+	return nil
+}
+
+// setRecord updates old, an existing record, in place to hold rec.
+func setRecord(ctx context.Context, provider *cloudflare.Provider, zone string, rec, old libdns.Record) error {
+	// With an ID, the provider updates the record without looking it up again.
+	rec.ID = old.ID
+	_, err := provider.SetRecords(ctx, zone, []libdns.Record{rec})
+	return err
+}
+
+// findZone returns the Cloudflare zone that domain belongs to, trying each
+// parent of domain in turn from the longest to the registrable domain.
+func findZone(ctx context.Context, provider *cloudflare.Provider, domain string) (string, error) {
+	// As in a zone file, "@" stands for the apex, so the rest is the zone.
+	if zone, ok := strings.CutPrefix(domain, "@."); ok {
+		err := resolveZone(ctx, provider, zone)
+		if err != nil && isZoneNotFound(err) {
+			return "", fmt.Errorf("%w for %s", errNoZone, domain)
+		}
+		if err != nil {
+			return "", fmt.Errorf("looking up zone %s: %w", zone, err)
+		}
+		explain("zone for %s is %s, named by the @ prefix and confirmed via the API", domain, zone)
+		return zone, nil
+	}
+	labels := strings.Split(domain, ".")
+	for i := 1; i < len(labels)-1; i++ {
+		candidate := strings.Join(labels[i:], ".")
+		err := resolveZone(ctx, provider, candidate)
+		if err == nil {
+			explain("zone for %s resolved to %s via the API, searching parent domains", domain, candidate)
+			return candidate, nil
+		}
+		if !isZoneNotFound(err) {
+			return "", fmt.Errorf("looking up zone %s: %w", candidate, err)
+		}
+		slog.Debug("not a zone", "name", candidate)
+		explain("%s is not a zone on this account", candidate)
+	}
+	return "", fmt.Errorf("%w for %s", errNoZone, domain)
+}
+
+var errNoZone = errors.New("no zone found")
+
+// resolveZone checks that zone is a zone the provider can see. Appending
+// no records makes the provider look the zone up, and cache it for later
+// calls, without listing its records. A missing zone isn't retryable, so
+// that still fails fast.
+func resolveZone(ctx context.Context, provider *cloudflare.Provider, zone string) error {
+	return withRetry(ctx, 3, func() error {
+		_, err := provider.AppendRecords(ctx, zone, nil)
+		return err
+	})
+}
+
+// recordName returns the name of domain's records within zone, where the
+// apex, written "@.<zone>", has an empty name.
+func recordName(domain, zone string) string {
+	name := libdns.RelativeName(domain, zone)
+	if name == "@" {
+		return ""
+	}
+	return name
+}
+
+// createName is the name to send when creating a record called name. The
+// provider leaves an empty name out of the request, which Cloudflare
+// rejects, so the apex is sent as "@" instead.
+func createName(name string) string {
+	if name == "" {
+		return "@"
+	}
+	return name
+}
+
+// isZoneNotFound reports whether err is the provider's error for a zone
+// name that doesn't match any zone the token can see.
+func isZoneNotFound(err error) bool {
+	return strings.Contains(err.Error(), "expected 1 zone, got 0")
+}
+
+// change is one step of a plan for a set of records: act is what's to be
+// done to rec, the record as wanted or, for a deletion, the one to delete,
+// and old is the existing record an update or unchanged record is based on.
+type change struct {
+	act action
+	rec libdns.Record
+	old libdns.Record
+}
+
+// planSet works out, without changing anything, how to make the records of
+// want's type at want's name hold exactly want's values, with want's TTLs.
+// Matching records are left alone, unwanted ones are updated in place to
+// missing values, any other missing ones are created (or skipped, under
+// updateOnly), and then any other unwanted ones are deleted.
+func planSet(zone string, existing, want []libdns.Record, updateOnly bool) ([]change, error) {
+	recordType, name := want[0].Type, want[0].Name
+	var have []libdns.Record
+	for _, rec := range existing {
+		if rec.Type == recordType && rec.Name == name {
+			have = append(have, rec)
+		}
+	}
+
+	var missing, extra []libdns.Record
+	for _, rec := range want {
+		if !slices.ContainsFunc(have, func(old libdns.Record) bool { return old.Value == rec.Value }) {
+			missing = append(missing, rec)
+		}
+	}
+	for _, rec := range have {
+		if !slices.ContainsFunc(want, func(w libdns.Record) bool { return w.Value == rec.Value }) {
+			extra = append(extra, rec)
+		}
+	}
+	// Other records of a type like TXT usually belong to something else,
+	// such as SPF or site verification, so they're left alone, and only
+	// replaced when there's just the one.
+	if !slices.Contains([]string{"A", "AAAA", "CNAME"}, recordType) {
+		switch {
+		case len(missing) == 0:
+			extra = nil
+		case len(extra) > 1:
+			return nil, fmt.Errorf("%d %s records at %s and none holds %q, so it's ambiguous which to replace; set CF_RECORD_ID to the one to update", len(extra), recordType, libdns.AbsoluteName(name, zone), missing[0].Value)
+		}
+	}
+
+	var plan []change
+	for _, rec := range want {
+		i := slices.IndexFunc(have, func(old libdns.Record) bool { return old.Value == rec.Value })
+		switch {
+		case i < 0:
+			// Missing, so it's handled below.
+		case have[i].TTL == rec.TTL:
+			plan = append(plan, change{act: actionUnchanged, rec: rec, old: have[i]})
+		default:
+			plan = append(plan, change{act: actionSettingsUpdated, rec: rec, old: have[i]})
+		}
+	}
+	// Reusing an unwanted record for a missing value, rather than deleting
+	// one and creating the other, means the name is never left without a
+	// record, and works under CF_UPDATE_ONLY too.
+	for len(missing) > 0 && len(extra) > 0 {
+		plan = append(plan, change{act: actionUpdated, rec: missing[0], old: extra[0]})
+		missing, extra = missing[1:], extra[1:]
+	}
+	for _, rec := range missing {
+		act := actionCreated
+		if updateOnly {
+			act = actionSkipped
+		}
+		plan = append(plan, change{act: act, rec: rec})
+	}
+	// Only records beyond those reused above are left here, so every wanted
+	// value already has a record.
+	for _, rec := range extra {
+		plan = append(plan, change{act: actionDeleted, rec: rec})
+	}
+	return plan, nil
+}
+
+// reconcileSet carries out planSet's plan for want, creating the missing
+// records together and then deleting the unwanted ones together. It
+// reports whether anything changed, and whether any missing record was
+// skipped under CF_UPDATE_ONLY.
+func reconcileSet(ctx context.Context, provider *cloudflare.Provider, zone string, existing, want []libdns.Record) (changed, skipped bool, err error) {
+	plan, err := planSet(zone, existing, want, os.Getenv("CF_UPDATE_ONLY") == "1")
+	if err != nil {
+		return false, false, err
+	}
+	recordType, fqdn := want[0].Type, libdns.AbsoluteName(want[0].Name, zone)
+	// Nothing in the set is changed unless all of its deletions fit.
+	deletes := 0
+	for _, c := range plan {
+		if c.act == actionDeleted {
+			deletes++
+		}
+	}
+	if err := reserveDeletes(deletes); err != nil {
+		return false, false, err
+	}
+
+	var create, remove []libdns.Record
+	for _, c := range plan {
+		switch c.act {
+		case actionUnchanged:
+			explain("found %s record %s with content %s; action: none", recordType, fqdn, c.rec.Value)
+			logUpsert(fqdn, c.rec, c.act, "", "match", "name")
+		case actionSettingsUpdated, actionUpdated:
+			if c.act == actionUpdated {
+				explain("found %s record %s with content %s; action: update to %s", recordType, fqdn, c.old.Value, c.rec.Value)
+			} else {
+				explain("found %s record %s with content %s and TTL %v; action: update the TTL to %v", recordType, fqdn, c.rec.Value, c.old.TTL, c.rec.TTL)
+			}
+			if err := setRecord(ctx, provider, zone, c.rec, c.old); err != nil {
+				return changed, skipped, err
+			}
+			logUpsert(fqdn, c.rec, c.act, c.old.Value, "match", "name")
+			changed = true
+		case actionSkipped:
+			explain("no %s record %s with content %s; action: skip, since CF_UPDATE_ONLY is set", recordType, fqdn, c.rec.Value)
+			logUpsert(fqdn, c.rec, c.act, "", "match", "name")
+			skipped = true
+		case actionCreated:
+			create = append(create, c.rec)
+		case actionDeleted:
+			remove = append(remove, c.rec)
+		}
+	}
+
+	if len(create) > 0 {
+		var recs []libdns.Record
+		for _, rec := range create {
+			explain("no %s record %s with content %s; action: create", recordType, fqdn, rec.Value)
+			rec.Name = createName(rec.Name)
+			recs = append(recs, rec)
+		}
+		if _, err := provider.AppendRecords(ctx, zone, recs); err != nil {
+			return changed, skipped, err
+		}
+		for _, rec := range create {
+			logUpsert(fqdn, rec, actionCreated, "", "match", "name")
+		}
+		changed = true
+	}
+
+	if len(remove) > 0 {
+		for _, rec := range remove {
+			explain("found %s record %s with content %s, which isn't wanted; action: delete", recordType, fqdn, rec.Value)
+		}
+		if _, err := provider.DeleteRecords(ctx, zone, remove); err != nil {
+			return true, skipped, err
+		}
+		for _, rec := range remove {
+			logUpsert(fqdn, rec, actionDeleted, "", "match", "name")
+		}
+		changed = true
+	}
+	return changed, skipped, nil
+}
+
+// updatePTR points the reverse DNS name of addr at target, in whichever
+// reverse zone on the account contains it. It reports whether the record
+// had to change.
+func updatePTR(ctx context.Context, provider *cloudflare.Provider, addr, target string, ttl time.Duration) (bool, error) {
+	ip := net.ParseIP(addr)
+	if ip == nil {
+		return false, fmt.Errorf("%q is not an IP address", addr)
+	}
+	fqdn := reverseName(ip)
+	start := time.Now()
+	zone, err := findZone(ctx, provider, fqdn)
+	if err != nil {
+		return false, err
+	}
+	slog.Debug("zone lookup took", "domain", fqdn, "duration", time.Since(start))
+	start = time.Now()
+	existing, err := provider.GetRecords(ctx, zone)
+	if err != nil {
+		return false, fmt.Errorf("listing records in %s: %w", zone, err)
+	}
+	slog.Debug("listing records took", "zone", zone, "duration", time.Since(start))
+	rec := libdns.Record{
+		Type:  "PTR",
+		Name:  libdns.RelativeName(fqdn, zone),
+		Value: target,
+		TTL:   ttl,
+	}
+	start = time.Now()
+	changed, _, err := reconcileSet(ctx, provider, zone, existing, []libdns.Record{rec})
+	if err != nil {
+		return changed, fmt.Errorf("setting PTR record: %w", err)
+	}
+	slog.Debug("upserted records", "domain", fqdn, "type", "PTR", "duration", time.Since(start))
+	return changed, nil
+}
+
+// addTXT adds a TXT record holding value at domain, leaving any other TXT
+// records there alone. It reports whether the record had to be created.
+func addTXT(ctx context.Context, provider *cloudflare.Provider, domain, value string, ttl time.Duration) (bool, error) {
+	zone, err := findZone(ctx, provider, domain)
+	if err != nil {
+		return false, err
+	}
+	existing, err := provider.GetRecords(ctx, zone)
+	if err != nil {
+		return false, fmt.Errorf("listing records in %s: %w", zone, err)
+	}
+	name := recordName(domain, zone)
+	if slices.ContainsFunc(existing, func(rec libdns.Record) bool {
+		return rec.Type == "TXT" && rec.Name == name && rec.Value == value
+	}) {
+		slog.Info("set record", "domain", domain, "type", "TXT", "value", value, "action", actionUnchanged)
+		return false, nil
+	}
+	rec := libdns.Record{Type: "TXT", Name: createName(name), Value: value, TTL: ttl}
+	if _, err := provider.AppendRecords(ctx, zone, []libdns.Record{rec}); err != nil {
+		return false, err
+	}
+	slog.Info("set record", "domain", domain, "type", "TXT", "value", value, "action", actionCreated)
+	return true, nil
+}
+
+// removeTXT deletes the TXT records at domain holding value, or all of
+// them if value is empty, and returns how many it deleted.
+func removeTXT(ctx context.Context, provider *cloudflare.Provider, domain, value string) (int, error) {
+	zone, err := findZone(ctx, provider, domain)
+	if err != nil {
+		return 0, err
+	}
+	existing, err := provider.GetRecords(ctx, zone)
+	if err != nil {
+		return 0, fmt.Errorf("listing records in %s: %w", zone, err)
+	}
+	name := recordName(domain, zone)
+	var recs []libdns.Record
+	for _, rec := range existing {
+		if rec.Type == "TXT" && rec.Name == name && (value == "" || rec.Value == value) {
+			recs = append(recs, rec)
+		}
+	}
+	if len(recs) == 0 {
+		return 0, nil
+	}
+	if err := reserveDeletes(len(recs)); err != nil {
+		return 0, err
+	}
+	if _, err := provider.DeleteRecords(ctx, zone, recs); err != nil {
+		return 0, err
+	}
+	for _, rec := range recs {
+		slog.Info("set record", "domain", domain, "type", "TXT", "value", rec.Value, "action", actionDeleted)
+	}
+	return len(recs), nil
+}
+
+// reverseName returns the in-addr.arpa or ip6.arpa name for ip.
+func reverseName(ip net.IP) string {
+	if ip4 := ip.To4(); ip4 != nil {
+		return fmt.Sprintf("%d.%d.%d.%d.in-addr.arpa", ip4[3], ip4[2], ip4[1], ip4[0])
+	}
+	const hexDigits = "0123456789abcdef"
+	var b strings.Builder
+	ip = ip.To16()
+	for i := len(ip) - 1; i >= 0; i-- {
+		b.WriteByte(hexDigits[ip[i]&0xf])
+		b.WriteByte('.')
+		b.WriteByte(hexDigits[ip[i]>>4])
+		b.WriteByte('.')
+	}
+	b.WriteString("ip6.arpa")
+	return b.String()
+}
+
+// clearCNAME makes room for address records at name, since Cloudflare won't
+// allow them next to a CNAME (including a flattened one at the apex).
+func clearCNAME(ctx context.Context, provider *cloudflare.Provider, zone, name string, existing []libdns.Record) error {
+	for _, rec := range existing {
+		if rec.Type != "CNAME" || rec.Name != name {
+			continue
+		}
+		fqdn := libdns.AbsoluteName(name, zone)
+		explain("found CNAME %s -> %s, which conflicts with the records to set", fqdn, rec.Value)
+		if os.Getenv("CF_REPLACE_CNAME") != "1" {
+			return fmt.Errorf("%s is a CNAME to %s; set CF_REPLACE_CNAME=1 to replace it", fqdn, rec.Value)
+		}
+		if err := reserveDeletes(1); err != nil {
+			return err
+		}
+		slog.Warn("deleting conflicting CNAME", "name", fqdn, "target", rec.Value)
+		if _, err := provider.DeleteRecords(ctx, zone, []libdns.Record{rec}); err != nil {
+			return fmt.Errorf("deleting CNAME %s: %w", fqdn, err)
+		}
+	}
+	return nil
+}
+
+func findRecord(recs []libdns.Record, recordType, name string) (libdns.Record, bool) {
+	for _, rec := range recs {
+		if rec.Type == recordType && rec.Name == name {
+			return rec, true
+		}
+	}
+	return libdns.Record{}, false
+}
+
+// deleteRecords deletes the recordType records at domain, after asking on
+// stdin unless yes is set.
+func deleteRecords(ctx context.Context, provider *cloudflare.Provider, domain, recordType string, yes bool) error {
+	zone, err := findZone(ctx, provider, domain)
+	if err != nil {
+		return err
+	}
+	existing, err := provider.GetRecords(ctx, zone)
+	if err != nil {
+		return fmt.Errorf("listing records in %s: %w", zone, err)
+	}
+	name := recordName(domain, zone)
+	var recs []libdns.Record
+	for _, rec := range existing {
+		if rec.Type == recordType && rec.Name == name {
+			recs = append(recs, rec)
+		}
+	}
+	if len(recs) == 0 {
+		return fmt.Errorf("no %s record at %s", recordType, domain)
+	}
+	for _, rec := range recs {
+		fmt.Printf("%s\t%s\t%s\n", rec.ID, rec.Type, rec.Value)
+	}
+	if !yes {
+		fmt.Printf("Delete %d %s record(s) at %s? [y/N] ", len(recs), recordType, domain)
+		answer, _ := bufio.NewReader(os.Stdin).ReadString('\n')
+		if a := strings.ToLower(strings.TrimSpace(answer)); a != "y" && a != "yes" {
+			return fmt.Errorf("not confirmed")
+		}
+	}
+	if err := reserveDeletes(len(recs)); err != nil {
+		return err
+	}
+	if _, err := provider.DeleteRecords(ctx, zone, recs); err != nil {
+		return err
+	}
+	for _, rec := range recs {
+		slog.Info("set record", "domain", domain, "type", rec.Type, "value", rec.Value, "action", actionDeleted)
+	}
+	return nil
+}
+
+// printRecords writes the ID, type, and value of each record at name, one
+// per line, separated by tabs.
+func printRecords(ctx context.Context, provider *cloudflare.Provider, zone, name string) error {
+	recs, err := provider.GetRecords(ctx, zone)
+	if err != nil {
+		return err
+	}
+	for _, rec := range recs {
+		if rec.Name == name {
+			fmt.Printf("%s\t%s\t%s\n", rec.ID, rec.Type, rec.Value)
+		}
+	}
+	return nil
+}
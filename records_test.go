@@ -0,0 +1,273 @@
+package main
+
+import (
+	"bytes"
+	"context"
+	"log"
+	"net"
+	"slices"
+	"strings"
+	"testing"
+	"time"
+
+	"github.com/libdns/cloudflare"
+	"github.com/libdns/libdns"
+)
+
+func TestRecordName(t *testing.T) {
+	for _, tt := range []struct {
+		domain, zone, want string
+	}{
+		{"home.example.com", "example.com", "home"},
+		{"a.b.example.com", "example.com", "a.b"},
+		{"@.example.com", "example.com", ""},
+		{"home.example.co.uk", "example.co.uk", "home"},
+	} {
+		if got := recordName(tt.domain, tt.zone); got != tt.want {
+			t.Errorf("recordName(%q, %q) = %q, want %q", tt.domain, tt.zone, got, tt.want)
+		}
+	}
+}
+
+func TestUpdateDomainCreatesApexRecord(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	provider := &cloudflare.Provider{APIToken: "test-token"}
+	records := []libdns.Record{{Type: "A", Value: "203.0.113.7", TTL: 5 * time.Minute}}
+
+	changed, _, err := updateDomain(context.Background(), provider, "@.example.com", records)
+	if err != nil {
+		t.Fatal(err)
+	}
+	if !changed {
+		t.Error("updateDomain reported no change")
+	}
+	if sent := cf.sent(); len(sent) != 1 || sent[0].Name != "@" {
+		t.Errorf("created records with bodies %+v, want one named @", sent)
+	}
+	recs := cf.list("example.com")
+	if len(recs) != 1 || recs[0].Name != "example.com" || recs[0].Content != "203.0.113.7" {
+		t.Errorf("records are %+v, want one A record at example.com", recs)
+	}
+
+	// The apex record that was created is found again by the next run.
+	cf.log()
+	changed, _, err = updateDomain(context.Background(), provider, "@.example.com", records)
+	if err != nil {
+		t.Fatal(err)
+	}
+	if changed {
+		t.Errorf("second updateDomain changed records, making requests %q", cf.log())
+	}
+}
+
+func TestUpdateDomainReconcilesRecords(t *testing.T) {
+	const ttl = 5 * time.Minute
+	for _, tt := range []struct {
+		name       string
+		updateOnly bool
+		have       []string
+		haveTTL    int
+		want       []string
+		wantMethod []string
+		wantValues []string
+	}{{
+		name:       "single record replaces duplicates",
+		have:       []string{"198.51.100.1", "198.51.100.2"},
+		want:       []string{"203.0.113.7"},
+		wantMethod: []string{"PATCH", "DELETE"},
+		wantValues: []string{"203.0.113.7"},
+	}, {
+		name:       "matching record is kept and the rest deleted",
+		have:       []string{"198.51.100.1", "203.0.113.7"},
+		want:       []string{"203.0.113.7"},
+		wantMethod: []string{"DELETE"},
+		wantValues: []string{"203.0.113.7"},
+	}, {
+		name:       "round robin",
+		have:       []string{"198.51.100.1", "203.0.113.7"},
+		want:       []string{"203.0.113.7", "203.0.113.8", "203.0.113.9"},
+		wantMethod: []string{"PATCH", "POST"},
+		wantValues: []string{"203.0.113.8", "203.0.113.7", "203.0.113.9"},
+	}, {
+		name:       "TTL is corrected",
+		have:       []string{"203.0.113.7"},
+		haveTTL:    3600,
+		want:       []string{"203.0.113.7"},
+		wantMethod: []string{"PATCH"},
+		wantValues: []string{"203.0.113.7"},
+	}, {
+		name:       "update only reuses records rather than deleting them",
+		updateOnly: true,
+		have:       []string{"198.51.100.1"},
+		want:       []string{"203.0.113.7", "203.0.113.8"},
+		wantMethod: []string{"PATCH"},
+		wantValues: []string{"203.0.113.7"},
+	}, {
+		name:       "update only leaves a name with no records alone",
+		updateOnly: true,
+		want:       []string{"203.0.113.7"},
+	}} {
+		t.Run(tt.name, func(t *testing.T) {
+			cf := newFakeCloudflare(t, "example.com")
+			if tt.updateOnly {
+				t.Setenv("CF_UPDATE_ONLY", "1")
+			}
+			haveTTL := tt.haveTTL
+			if haveTTL == 0 {
+				haveTTL = int(ttl.Seconds())
+			}
+			for _, value := range tt.have {
+				cf.add("example.com", "A", "home.example.com", value, haveTTL)
+			}
+			var records []libdns.Record
+			for _, value := range tt.want {
+				records = append(records, libdns.Record{Type: "A", Value: value, TTL: ttl})
+			}
+			provider := &cloudflare.Provider{APIToken: "test-token"}
+			if _, _, err := updateDomain(context.Background(), provider, "home.example.com", records); err != nil {
+				t.Fatal(err)
+			}
+
+			var methods []string
+			for _, req := range cf.log() {
+				if method, _, _ := strings.Cut(req, " "); method != "GET" {
+					methods = append(methods, method)
+				}
+			}
+			if !slices.Equal(methods, tt.wantMethod) {
+				t.Errorf("made %q requests, want %q", methods, tt.wantMethod)
+			}
+			var values []string
+			for _, rec := range cf.list("example.com") {
+				values = append(values, rec.Content)
+				if rec.TTL != int(ttl.Seconds()) {
+					t.Errorf("record %s has TTL %d, want %v", rec.Content, rec.TTL, ttl)
+				}
+			}
+			if !slices.Equal(values, tt.wantValues) {
+				t.Errorf("records hold %q, want %q", values, tt.wantValues)
+			}
+		})
+	}
+}
+
+func TestUpdateDomainLogsTTLFix(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	cf.add("example.com", "A", "home.example.com", "203.0.113.7", 3600)
+	var buf bytes.Buffer
+	oldOutput := log.Writer()
+	log.SetOutput(&buf)
+	t.Cleanup(func() { log.SetOutput(oldOutput) })
+
+	provider := &cloudflare.Provider{APIToken: "test-token"}
+	records := []libdns.Record{{Type: "A", Value: "203.0.113.7", TTL: 5 * time.Minute}}
+	changed, _, err := updateDomain(context.Background(), provider, "home.example.com", records)
+	if err != nil {
+		t.Fatal(err)
+	}
+	if !changed {
+		t.Error("updateDomain reported no change for a TTL fix")
+	}
+	if out := buf.String(); !strings.Contains(out, "action=settings_updated") || strings.Contains(out, "from=") {
+		t.Errorf("TTL fix logged as:\n%s\nwant action=settings_updated with no from", out)
+	}
+}
+
+func TestUpdateDomainLeavesOtherTXTRecords(t *testing.T) {
+	ctx := context.Background()
+	records := []libdns.Record{{Type: "TXT", Value: "token", TTL: 5 * time.Minute}}
+
+	// A record already holding the value is enough, whatever else is there.
+	cf := newFakeCloudflare(t, "example.com")
+	cf.add("example.com", "TXT", "example.com", "v=spf1 -all", 300)
+	cf.add("example.com", "TXT", "example.com", "token", 300)
+	provider := &cloudflare.Provider{APIToken: "test-token"}
+	if _, _, err := updateDomain(ctx, provider, "@.example.com", records); err != nil {
+		t.Fatal(err)
+	}
+	if got := cf.list("example.com"); len(got) != 2 {
+		t.Errorf("records are %+v, want both TXT records kept", got)
+	}
+
+	// With several other values, which one to replace is ambiguous.
+	cf = newFakeCloudflare(t, "example.com")
+	cf.add("example.com", "TXT", "example.com", "v=spf1 -all", 300)
+	cf.add("example.com", "TXT", "example.com", "google-site-verification=abc", 300)
+	provider = &cloudflare.Provider{APIToken: "test-token"}
+	if _, _, err := updateDomain(ctx, provider, "@.example.com", records); err == nil {
+		t.Error("updateDomain picked one of several TXT records to replace")
+	}
+	for _, req := range cf.log() {
+		if !strings.HasPrefix(req, "GET ") {
+			t.Errorf("ambiguous update made request %s", req)
+		}
+	}
+}
+
+func TestUpdateDomainNullResult(t *testing.T) {
+	cf := newFakeCloudflare(t, "example.com")
+	cf.mu.Lock()
+	cf.nullLists = true
+	cf.mu.Unlock()
+	provider := &cloudflare.Provider{APIToken: "test-token"}
+	records := []libdns.Record{{Type: "A", Value: "203.0.113.7", TTL: 5 * time.Minute}}
+
+	// A null list of records is no records, so the record is created.
+	changed, _, err := updateDomain(context.Background(), provider, "home.example.com", records)
+	if err != nil {
+		t.Fatal(err)
+	}
+	if recs := cf.list("example.com"); !changed || len(recs) != 1 {
+		t.Errorf("updateDomain changed %v, leaving records %+v; want one record created", changed, recs)
+	}
+}
+
+func TestMaxDeletes(t *testing.T) {
+	ctx := context.Background()
+	cf := newFakeCloudflare(t, "example.com")
+	maxDeletes = 2
+	for _, value := range []string{"198.51.100.1", "198.51.100.2", "198.51.100.3", "198.51.100.4"} {
+		cf.add("example.com", "A", "home.example.com", value, 300)
+	}
+	cf.add("example.com", "TXT", "_acme-challenge.example.com", "token1", 300)
+	cf.add("example.com", "TXT", "_acme-challenge.example.com", "token2", 300)
+	provider := &cloudflare.Provider{APIToken: "test-token"}
+	records := []libdns.Record{{Type: "A", Value: "203.0.113.7", TTL: 5 * time.Minute}}
+
+	// One stray record would be reused and three deleted, which is over the
+	// limit, so nothing changes at all.
+	if _, _, err := updateDomain(ctx, provider, "home.example.com", records); err == nil || !strings.Contains(err.Error(), "CF_MAX_DELETES") {
+		t.Errorf("updateDomain = %v, want an error about CF_MAX_DELETES", err)
+	}
+	for _, req := range cf.log() {
+		if !strings.HasPrefix(req, "GET ") {
+			t.Errorf("update over the limit made request %s", req)
+		}
+	}
+
+	// Deletions that fit are made, and count towards the limit.
+	if n, err := removeTXT(ctx, provider, "_acme-challenge.example.com", ""); err != nil || n != 2 {
+		t.Errorf("removeTXT = %d, %v; want 2 records deleted", n, err)
+	}
+	if err := deleteRecords(ctx, provider, "home.example.com", "A", true); err == nil || !strings.Contains(err.Error(), "CF_MAX_DELETES") {
+		t.Errorf("deleteRecords = %v, want an error about CF_MAX_DELETES", err)
+	}
+	if recs := cf.list("example.com"); len(recs) != 4 {
+		t.Errorf("records are %+v, want the four A records left alone", recs)
+	}
+}
+
+func TestReverseName(t *testing.T) {
+	for _, tt := range []struct {
+		ip, want string
+	}{
+		{"203.0.113.7", "7.113.0.203.in-addr.arpa"},
+		{"::ffff:198.51.100.1", "1.100.51.198.in-addr.arpa"},
+		{"2001:db8::1", "1.0." + strings.Repeat("0.", 22) + "8.b.d.0.1.0.0.2.ip6.arpa"},
+		{"2001:db8::abcd", "d.c.b.a." + strings.Repeat("0.", 20) + "8.b.d.0.1.0.0.2.ip6.arpa"},
+	} {
+		if got := reverseName(net.ParseIP(tt.ip)); got != tt.want {
+			t.Errorf("reverseName(%s) = %q, want %q", tt.ip, got, tt.want)
+		}
+	}
+}
@@ -0,0 +1,85 @@
+package main
+
+import (
+	"cmp"
+	"io"
+	"log"
+	"slices"
+	"strings"
+)
+
+// redactDomains is set by CF_REDACT_DOMAINS=1, for logs that get shared.
+var redactDomains bool
+
+// setupRedaction masks each of domains in everything written to the log,
+// the log file and -explain output. It's a no-op unless redactDomains is set.
+func setupRedaction(domains []string) {
+	if !redactDomains {
+		return
+	}
+	// strings.Replacer tries its patterns in order, so longer names go
+	// first in case one domain is a suffix of another.
+	domains = slices.Clone(domains)
+	slices.SortFunc(domains, func(a, b string) int { return cmp.Compare(len(b), len(a)) })
+	var pairs []string
+	for _, d := range domains {
+		pairs = append(pairs, d, maskDomain(d))
+	}
+	redactOutput(strings.NewReplacer(pairs...))
+}
+
+// hideSecret masks secret in everything written to the log, the log file
+// and -explain output, in case an error or debug message ever includes it.
+func hideSecret(secret string) {
+	redactOutput(strings.NewReplacer(secret, redactSecret(secret)))
+}
+
+// redactSecret shows only the ends of secret, which is enough to tell
+// tokens apart.
+func redactSecret(secret string) string {
+	if len(secret) < 16 {
+		return "****"
+	}
+	return secret[:4] + "****" + secret[len(secret)-4:]
+}
+
+func redactOutput(r *strings.Replacer) {
+	log.SetOutput(&redactingWriter{w: log.Writer(), r: r})
+	explainOut = &redactingWriter{w: explainOut, r: r}
+}
+
+// maskDomain hides all but the start of the first label of domain, so
+// "home.example.com" becomes "ho***.example.com".
+func maskDomain(domain string) string {
+	label, rest, _ := strings.Cut(domain, ".")
+	if len(label) > 2 {
+		label = label[:2]
+	}
+	if rest == "" {
+		return label + "***"
+	}
+	return label + "***." + rest
+}
+
+// redactName masks name, the name of a record within its zone, when
+// domains are being redacted.
+func redactName(name string) string {
+	if !redactDomains {
+		return name
+	}
+	return maskDomain(name)
+}
+
+// redactingWriter relies on log writing each message with a single Write,
+// so names are never split across calls.
+type redactingWriter struct {
+	w io.Writer
+	r *strings.Replacer
+}
+
+func (rw *redactingWriter) Write(p []byte) (int, error) {
+	if _, err := rw.r.WriteString(rw.w, string(p)); err != nil {
+		return 0, err
+	}
+	return len(p), nil
+}
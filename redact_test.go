@@ -0,0 +1,96 @@
+package main
+
+import (
+	"bytes"
+	"log"
+	"log/slog"
+	"net/http"
+	"net/http/httptest"
+	"strings"
+	"testing"
+)
+
+func TestTokenNeverLogged(t *testing.T) {
+	const token = "test-token-0123456789abcdef"
+	// An API that's down and, unhelpfully, quotes the credentials back in
+	// its error, which the run then logs.
+	srv := httptest.NewServer(http.HandlerFunc(func(w http.ResponseWriter, r *http.Request) {
+		writeError(w, http.StatusServiceUnavailable, "unavailable for "+r.Header.Get("Authorization"))
+	}))
+	t.Cleanup(srv.Close)
+	isolateEnv(t)
+	newFakeTrace(t, "ip=203.0.113.7\n")
+	t.Setenv("CLOUDFLARE_API_TOKEN", token)
+	t.Setenv("CF_API_BASE", srv.URL+defaultAPIPath)
+	t.Setenv("CF_DEFER_ON_OUTAGE", "1")
+
+	var buf bytes.Buffer
+	oldOutput, oldExplain := log.Writer(), explainOut
+	log.SetOutput(&buf)
+	explainOut = &buf
+	t.Cleanup(func() {
+		log.SetOutput(oldOutput)
+		explainOut = oldExplain
+		slog.SetLogLoggerLevel(slog.LevelInfo)
+	})
+
+	runDyncf(t, "-debug", "-explain", "-ipv4-only", "-dns-domain", "home.example.com")
+	out := buf.String()
+	if !strings.Contains(out, "unavailable for Bearer "+redactSecret(token)) {
+		t.Errorf("output doesn't include the API's error with the token masked:\n%s", out)
+	}
+	if strings.Contains(out, token) {
+		t.Errorf("output includes the API token:\n%s", out)
+	}
+}
+
+func TestRedactingWriter(t *testing.T) {
+	for _, tt := range []struct {
+		pairs   []string
+		in, out string
+	}{
+		{[]string{"secret", "s****t"}, "token secret here\n", "token s****t here\n"},
+		{[]string{"secret", "s****t"}, "nothing to hide\n", "nothing to hide\n"},
+		{[]string{"a.example.com", "a***.example.com", "example.com", "ex***"}, "a.example.com and example.com", "a***.example.com and ex***"},
+		{[]string{"secret", "s****t"}, "", ""},
+	} {
+		var buf bytes.Buffer
+		w := &redactingWriter{w: &buf, r: strings.NewReplacer(tt.pairs...)}
+		n, err := w.Write([]byte(tt.in))
+		if err != nil || n != len(tt.in) {
+			t.Errorf("Write(%q) = %d, %v; want %d, nil", tt.in, n, err, len(tt.in))
+		}
+		if got := buf.String(); got != tt.out {
+			t.Errorf("Write(%q) wrote %q, want %q", tt.in, got, tt.out)
+		}
+	}
+}
+
+func TestRedactSecret(t *testing.T) {
+	for _, tt := range []struct {
+		in, want string
+	}{
+		{"", "****"},
+		{"short-token", "****"},
+		{"0123456789abcdef", "0123****cdef"},
+		{"test-token-0123456789abcdef", "test****cdef"},
+	} {
+		if got := redactSecret(tt.in); got != tt.want {
+			t.Errorf("redactSecret(%q) = %q, want %q", tt.in, got, tt.want)
+		}
+	}
+}
+
+func TestMaskDomain(t *testing.T) {
+	for _, tt := range []struct {
+		in, want string
+	}{
+		{"home.example.com", "ho***.example.com"},
+		{"a.example.com", "a***.example.com"},
+		{"localhost", "lo***"},
+	} {
+		if got := maskDomain(tt.in); got != tt.want {
+			t.Errorf("maskDomain(%q) = %q, want %q", tt.in, got, tt.want)
+		}
+	}
+}
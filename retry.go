@@ -0,0 +1,73 @@
+package main
+
+import (
+	"context"
+	"encoding/json"
+	"errors"
+	"log/slog"
+	"math/rand/v2"
+	"net"
+	"regexp"
+	"strconv"
+	"time"
+)
+
+// withRetry calls f up to attempts times, backing off exponentially with
+// jitter between tries, until it succeeds or fails with an error that
+// retryable rejects.
+func withRetry(ctx context.Context, attempts int, f func() error) error {
+	delay := 500 * time.Millisecond
+	for i := 1; ; i++ {
+		err := f()
+		if err == nil || i >= attempts || !retryable(err) {
+			return err
+		}
+		sleep := delay/2 + rand.N(delay)
+		slog.Warn("retrying after error", "attempt", i, "delay", sleep, "err", err)
+		timer := time.NewTimer(sleep)
+		select {
+		case <-ctx.Done():
+			timer.Stop()
+			return err
+		case <-timer.C:
+		}
+		delay *= 2
+	}
+}
+
+// The provider reports API failures as "... HTTP <status>: ...".
+var httpStatusPattern = regexp.MustCompile(`HTTP (\d{3})`)
+
+// retryable reports whether err looks transient: a network error, an
+// HTTP 429 or 5xx from the API, or a non-JSON body such as a gateway's
+// HTML error page.
+func retryable(err error) bool {
+	if errors.Is(err, context.Canceled) || errors.Is(err, context.DeadlineExceeded) {
+		return false
+	}
+	var netErr net.Error
+	var syntaxErr *json.SyntaxError
+	if errors.As(err, &netErr) || errors.As(err, &syntaxErr) {
+		return true
+	}
+	if m := httpStatusPattern.FindStringSubmatch(err.Error()); m != nil {
+		status, _ := strconv.Atoi(m[1])
+		return status == 429 || status >= 500
+	}
+	return false
+}
+
+// outage reports whether err looks like Cloudflare itself failing rather
+// than the request: a 5xx from the API, or a non-JSON body such as a
+// maintenance page.
+func outage(err error) bool {
+	var syntaxErr *json.SyntaxError
+	if errors.As(err, &syntaxErr) {
+		return true
+	}
+	if m := httpStatusPattern.FindStringSubmatch(err.Error()); m != nil {
+		status, _ := strconv.Atoi(m[1])
+		return status >= 500
+	}
+	return false
+}
@@ -0,0 +1,88 @@
+package main
+
+import (
+	"encoding/binary"
+	"net"
+	"testing"
+)
+
+// stunMessage builds a STUN message of msgType holding attrs.
+func stunMessage(msgType uint16, txID []byte, attrs ...[]byte) []byte {
+	msg := make([]byte, 20)
+	binary.BigEndian.PutUint16(msg[0:], msgType)
+	binary.BigEndian.PutUint32(msg[4:], stunMagicCookie)
+	copy(msg[8:], txID)
+	for _, attr := range attrs {
+		msg = append(msg, attr...)
+	}
+	binary.BigEndian.PutUint16(msg[2:], uint16(len(msg)-20))
+	return msg
+}
+
+// stunAttr builds an attribute, padded to a multiple of 4 bytes.
+func stunAttr(attrType uint16, value []byte) []byte {
+	attr := make([]byte, 4, 4+len(value)+3)
+	binary.BigEndian.PutUint16(attr[0:], attrType)
+	binary.BigEndian.PutUint16(attr[2:], uint16(len(value)))
+	attr = append(attr, value...)
+	for len(attr)%4 != 0 {
+		attr = append(attr, 0)
+	}
+	return attr
+}
+
+// stunAddrValue builds a MAPPED-ADDRESS value for ip, or an
+// XOR-MAPPED-ADDRESS value if mask is set.
+func stunAddrValue(ip net.IP, mask []byte) []byte {
+	family, raw := byte(0x02), ip.To16()
+	if ip4 := ip.To4(); ip4 != nil {
+		family, raw = 0x01, ip4
+	}
+	value := []byte{0, family, 0x0d, 0x96}
+	for i, b := range raw {
+		if i < len(mask) {
+			b ^= mask[i]
+		}
+		value = append(value, b)
+	}
+	return value
+}
+
+func TestParseSTUNResponse(t *testing.T) {
+	txID := []byte("0123456789ab")
+	otherID := []byte("ba9876543210")
+	xorMask := append([]byte{0x21, 0x12, 0xa4, 0x42}, txID...)
+	v4, v6, mapped := net.ParseIP("203.0.113.7"), net.ParseIP("2001:db8::7"), net.ParseIP("198.51.100.1")
+	software := stunAttr(0x8022, []byte("test"))
+
+	truncated := stunMessage(stunBindingSuccess, txID, stunAttr(stunXorMappedAddress, stunAddrValue(v4, xorMask)))
+	truncated = truncated[:len(truncated)-4]
+
+	for _, tt := range []struct {
+		name string
+		msg  []byte
+		want net.IP // nil for an error
+	}{
+		{"XOR-MAPPED-ADDRESS IPv4", stunMessage(stunBindingSuccess, txID, stunAttr(stunXorMappedAddress, stunAddrValue(v4, xorMask))), v4},
+		{"XOR-MAPPED-ADDRESS IPv6", stunMessage(stunBindingSuccess, txID, stunAttr(stunXorMappedAddress, stunAddrValue(v6, xorMask))), v6},
+		{"MAPPED-ADDRESS", stunMessage(stunBindingSuccess, txID, software, stunAttr(stunMappedAddress, stunAddrValue(mapped, nil))), mapped},
+		{"XOR-MAPPED-ADDRESS preferred", stunMessage(stunBindingSuccess, txID,
+			stunAttr(stunMappedAddress, stunAddrValue(mapped, nil)),
+			stunAttr(stunXorMappedAddress, stunAddrValue(v4, xorMask))), v4},
+		{"no address", stunMessage(stunBindingSuccess, txID, software), nil},
+		{"wrong transaction ID", stunMessage(stunBindingSuccess, otherID, stunAttr(stunMappedAddress, stunAddrValue(mapped, nil))), nil},
+		{"error response", stunMessage(0x0111, txID), nil},
+		{"truncated", truncated, nil},
+		{"too short", []byte{0x01, 0x01, 0, 0}, nil},
+		{"malformed address", stunMessage(stunBindingSuccess, txID, stunAttr(stunXorMappedAddress, []byte{0, 0x01, 0, 0})), nil},
+	} {
+		got, err := parseSTUNResponse(tt.msg, txID)
+		if tt.want == nil {
+			if err == nil {
+				t.Errorf("%s: parseSTUNResponse = %v, want an error", tt.name, got)
+			}
+		} else if err != nil || !got.Equal(tt.want) {
+			t.Errorf("%s: parseSTUNResponse = %v, %v; want %v", tt.name, got, err, tt.want)
+		}
+	}
+}
@@ -0,0 +1,71 @@
+package main
+
+import (
+	"context"
+	"fmt"
+	"log/slog"
+	"net"
+	"slices"
+	"strings"
+	"time"
+)
+
+const (
+	publicResolver = "1.1.1.1:53"
+	verifyInterval = 10 * time.Second
+)
+
+// waitForValue polls a public resolver until it returns value among the
+// recordType records for domain, or ctx is done. Asking a public resolver
+// rather than the system one avoids a stale answer cached on the local
+// network.
+func waitForValue(ctx context.Context, domain, recordType, value string) error {
+	resolver := &net.Resolver{
+		PreferGo: true,
+		Dial: func(ctx context.Context, network, _ string) (net.Conn, error) {
+			return (&net.Dialer{}).DialContext(ctx, network, publicResolver)
+		},
+	}
+	host := strings.TrimPrefix(domain, "@.")
+	start := time.Now()
+	for {
+		values, err := lookupValues(ctx, resolver, host, recordType)
+		if err == nil && slices.Contains(values, value) {
+			slog.Info("record is live", "domain", domain, "type", recordType, "value", value, "after", time.Since(start).Round(time.Second))
+			return nil
+		}
+		slog.Debug("record not visible yet", "domain", domain, "type", recordType, "found", values, "err", err)
+		timer := time.NewTimer(verifyInterval)
+		select {
+		case <-ctx.Done():
+			timer.Stop()
+			return ctx.Err()
+		case <-timer.C:
+		}
+	}
+}
+
+func lookupValues(ctx context.Context, resolver *net.Resolver, domain, recordType string) ([]string, error) {
+	switch recordType {
+	case "A", "AAAA":
+		network := "ip4"
+		if recordType == "AAAA" {
+			network = "ip6"
+		}
+		ips, err := resolver.LookupIP(ctx, network, domain)
+		var values []string
+		for _, ip := range ips {
+			values = append(values, ip.String())
+		}
+		return values, err
+	case "TXT":
+		return resolver.LookupTXT(ctx, domain)
+	default:
+		return nil, fmt.Errorf("can't look up %s records", recordType)
+	}
+}
+
+// canVerify reports whether waitForValue can check records of recordType.
+func canVerify(recordType string) bool {
+	return recordType == "A" || recordType == "AAAA" || recordType == "TXT"
+}
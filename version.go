@@ -0,0 +1,39 @@
+package main
+
+import (
+	"runtime/debug"
+	"strings"
+)
+
+// versionString describes this build using the module version and the VCS
+// details the Go toolchain embeds at build time.
+func versionString() string {
+	info, ok := debug.ReadBuildInfo()
+	if !ok {
+		return "dyncf (unknown version)"
+	}
+	parts := []string{"dyncf", info.Main.Version}
+	var revision, commitTime string
+	var modified bool
+	for _, setting := range info.Settings {
+		switch setting.Key {
+		case "vcs.revision":
+			revision = setting.Value
+		case "vcs.time":
+			commitTime = setting.Value
+		case "vcs.modified":
+			modified = setting.Value == "true"
+		}
+	}
+	if revision != "" {
+		if modified {
+			revision += "-dirty"
+		}
+		parts = append(parts, "commit", revision)
+	}
+	if commitTime != "" {
+		parts = append(parts, "from", commitTime)
+	}
+	parts = append(parts, "built with", info.GoVersion)
+	return strings.Join(parts, " ")
+}